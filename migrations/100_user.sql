@@ -4,5 +4,5 @@ CREATE TABLE IF NOT EXISTS "user" (
   password TEXT NOT NULL,
   salt TEXT NOT NULL,
   time_created TIMESTAMPTZ NOT NULL,
-  last_updated TIMESTAMPTZ NOT NULL,
+  last_updated TIMESTAMPTZ NOT NULL
 )
\ No newline at end of file
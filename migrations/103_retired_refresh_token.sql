@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS "retired_refresh_token" (
+  refresh_token TEXT PRIMARY KEY,
+  token_id INT NOT NULL REFERENCES token(id) ON DELETE CASCADE,
+  user_id INT NOT NULL,
+  time_retired TIMESTAMPTZ NOT NULL
+);
\ No newline at end of file
@@ -0,0 +1,8 @@
+-- Tokens are now stored as base64 encoded SHA-256 digests.
+-- Converts the plaintext tokens of existing sessions so they stay valid.
+UPDATE "token" SET
+  auth_token = encode(sha256(convert_to(auth_token, 'UTF8')), 'base64'),
+  refresh_token = encode(sha256(convert_to(refresh_token, 'UTF8')), 'base64');
+
+UPDATE "retired_refresh_token" SET
+  refresh_token = encode(sha256(convert_to(refresh_token, 'UTF8')), 'base64');
\ No newline at end of file
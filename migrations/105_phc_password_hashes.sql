@@ -0,0 +1,6 @@
+-- Passwords are now stored as self-describing PHC strings, so the salt lives inside the password column.
+-- Existing PBKDF2-SHA512 hashes (1000 iterations, base64 salt and hash) are rewritten in place and keep verifying.
+UPDATE "user" SET
+  password = '$pbkdf2-sha512$i=1000,l=64$' || rtrim(salt, '=') || '$' || rtrim(password, '=');
+
+ALTER TABLE "user" DROP COLUMN salt;
\ No newline at end of file
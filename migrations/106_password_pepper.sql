@@ -0,0 +1,2 @@
+-- Key id of the application pepper each password was hashed with. NULL for hashes without a pepper.
+ALTER TABLE "user" ADD COLUMN pepper_key_id TEXT;
\ No newline at end of file
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS "password_history" (
+  id SERIAL PRIMARY KEY,
+  user_id INT NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+  password TEXT NOT NULL,
+  pepper_key_id TEXT,
+  time_created TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS password_history_user_id_idx ON "password_history" (user_id, time_created);
\ No newline at end of file
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS "credential_verification" (
+  id SERIAL PRIMARY KEY,
+  user_id INT NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+  credential_type VARCHAR NOT NULL,
+  credential VARCHAR NOT NULL,
+  code TEXT NOT NULL,
+  attempts INT NOT NULL,
+  time_created TIMESTAMPTZ NOT NULL,
+  expires_at TIMESTAMPTZ NOT NULL,
+  UNIQUE(user_id, credential_type)
+);
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- Credentials are unique per type, so a username can be the same text as someone else's phone number.
+-- credential_credential_key is the constraint created by UNIQUE(credential) in 102_credential.sql.
+ALTER TABLE "credential" DROP CONSTRAINT IF EXISTS credential_credential_key;
+
+CREATE UNIQUE INDEX IF NOT EXISTS credential_type_credential_idx ON "credential" (credential_type, credential);
\ No newline at end of file
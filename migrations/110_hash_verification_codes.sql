@@ -0,0 +1,3 @@
+-- Verification codes are now salted hashes instead of SHA-256 digests. Pending codes expire within minutes anyway,
+-- so they are dropped instead of converted and their users request a new one.
+DELETE FROM "credential_verification";
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Session lookups match a user's token digests, index them instead of scanning every session of the user.
+CREATE INDEX IF NOT EXISTS token_user_id_auth_token_idx ON "token" (user_id, auth_token);
+CREATE INDEX IF NOT EXISTS token_user_id_refresh_token_idx ON "token" (user_id, refresh_token);
\ No newline at end of file
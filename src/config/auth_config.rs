@@ -0,0 +1,322 @@
+use crate::config::password_policy::PasswordPolicy;
+use crate::domain::credential::CredentialType;
+use crate::resources::expirations::{
+    AUTH_TOKEN_EXPIRATION_TIME_MILLIS, MAX_SESSION_AGE_MILLIS,
+    REFRESH_TOKEN_EXPIRATION_TIME_MILLIS, VERIFICATION_CODE_EXPIRATION_TIME_MILLIS,
+};
+use crate::resources::hashing::MAX_CONCURRENT_HASHES;
+use crate::resources::variable_lengths::{
+    MAX_CREDENTIALS_PER_USER, MAX_EMAIL_LENGTH, MAX_NAME_LENGTH, MAX_PASSWORD_LENGTH,
+    MAX_PHONE_NUMBER_LENGTH, MAX_USERNAME_LENGTH, MAX_VERIFICATION_ATTEMPTS, MIN_EMAIL_LENGTH,
+    MIN_NAME_LENGTH, MIN_PASSWORD_LENGTH, MIN_PHONE_NUMBER_LENGTH, MIN_USERNAME_LENGTH,
+    VERIFICATION_CODE_LENGTH,
+};
+use crate::utils::password_hasher::{Argon2idHasher, PasswordHasher};
+use crate::validation::breached_passwords::BreachedPasswordList;
+use log::warn;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// Policy used by every service function: token lifetimes, length limits, hashing cost and credential rules.
+/// `AuthConfig::default()` matches the constants in `resources`. Use `AuthConfig::builder()` to change any of them.
+#[derive(Debug, Clone)]
+pub struct AuthConfig {
+    pub auth_token_expiration_time_millis: i64,
+    pub refresh_token_expiration_time_millis: i64,
+    pub max_session_age_millis: i64,
+    pub min_email_length: usize,
+    pub max_email_length: usize,
+    pub min_phone_number_length: usize,
+    pub max_phone_number_length: usize,
+    pub min_username_length: usize,
+    pub max_username_length: usize,
+    pub min_name_length: usize,
+    pub max_name_length: usize,
+    pub min_password_length: usize,
+    pub max_password_length: usize,
+    pub password_policy: PasswordPolicy,
+    /// Breached passwords new passwords are checked against. `None` (the default) skips the check.
+    pub breached_passwords: Option<Arc<dyn BreachedPasswordList>>,
+    /// What happens when `breached_passwords` can't be read. `false` (the default) fails the request with
+    /// `ERROR.CHECKING_BREACHED_PASSWORDS`, `true` logs the error and accepts the password as not breached.
+    pub breached_passwords_fail_open: bool,
+    /// Hasher for new passwords. Hashes made by the other built-in algorithms, or with other parameters, still verify.
+    pub password_hasher: Arc<dyn PasswordHasher>,
+    /// Secret peppers mixed into password hashes, oldest first. New hashes use the last one,
+    /// the older ones are kept so the hashes made with them still verify. Empty by default.
+    pub password_peppers: Vec<PasswordPepper>,
+    /// Slots for password hashing, which runs on tokio's blocking threads. Shared by every clone of this config,
+    /// so it caps the hashes computed at the same time across the whole service.
+    pub hashing_permits: Arc<Semaphore>,
+    pub max_credentials_per_user: usize,
+    /// Credential types that can only log in once verified. Empty by default.
+    pub require_verified_credentials: Vec<CredentialType>,
+    /// ISO 3166 region (`"US"`, `"DO"`...) of phone numbers written without a `+` country code.
+    /// `None` (the default) only turns numbers that have one into E.164.
+    pub default_phone_region: Option<String>,
+    pub verification_code_expiration_time_millis: i64,
+    /// Digits of the codes sent to phone numbers. Emails get a link token instead.
+    pub verification_code_length: usize,
+    /// Wrong codes allowed before the verification has to be requested again.
+    pub max_verification_attempts: i32,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            auth_token_expiration_time_millis: AUTH_TOKEN_EXPIRATION_TIME_MILLIS,
+            refresh_token_expiration_time_millis: REFRESH_TOKEN_EXPIRATION_TIME_MILLIS,
+            max_session_age_millis: MAX_SESSION_AGE_MILLIS,
+            min_email_length: MIN_EMAIL_LENGTH,
+            max_email_length: MAX_EMAIL_LENGTH,
+            min_phone_number_length: MIN_PHONE_NUMBER_LENGTH,
+            max_phone_number_length: MAX_PHONE_NUMBER_LENGTH,
+            min_username_length: MIN_USERNAME_LENGTH,
+            max_username_length: MAX_USERNAME_LENGTH,
+            min_name_length: MIN_NAME_LENGTH,
+            max_name_length: MAX_NAME_LENGTH,
+            min_password_length: MIN_PASSWORD_LENGTH,
+            max_password_length: MAX_PASSWORD_LENGTH,
+            password_policy: PasswordPolicy::default(),
+            breached_passwords: None,
+            breached_passwords_fail_open: false,
+            password_hasher: Arc::new(Argon2idHasher::default()),
+            password_peppers: Vec::new(),
+            hashing_permits: Arc::new(Semaphore::new(MAX_CONCURRENT_HASHES)),
+            max_credentials_per_user: MAX_CREDENTIALS_PER_USER,
+            require_verified_credentials: Vec::new(),
+            default_phone_region: None,
+            verification_code_expiration_time_millis: VERIFICATION_CODE_EXPIRATION_TIME_MILLIS,
+            verification_code_length: VERIFICATION_CODE_LENGTH,
+            max_verification_attempts: MAX_VERIFICATION_ATTEMPTS,
+        }
+    }
+}
+
+impl AuthConfig {
+    pub fn builder() -> AuthConfigBuilder {
+        AuthConfigBuilder::default()
+    }
+
+    /// Pepper new password hashes are made with.
+    pub fn get_current_pepper(&self) -> Option<&PasswordPepper> {
+        self.password_peppers.last()
+    }
+
+    pub fn get_pepper(&self, key_id: &str) -> Option<&PasswordPepper> {
+        self.password_peppers
+            .iter()
+            .find(|pepper| pepper.key_id == key_id)
+    }
+
+    /// Usernames can't be verified, so they're never required to be.
+    pub fn requires_verified_credential(&self, credential_type: &CredentialType) -> bool {
+        *credential_type != CredentialType::Username
+            && self.require_verified_credentials.contains(credential_type)
+    }
+
+    pub fn get_max_credential_length(&self, credential_type: &CredentialType) -> usize {
+        match credential_type {
+            CredentialType::PhoneNumber => self.max_phone_number_length,
+            CredentialType::Email => self.max_email_length,
+            CredentialType::Username => self.max_username_length,
+        }
+    }
+
+    pub fn get_min_credential_length(&self, credential_type: &CredentialType) -> usize {
+        match credential_type {
+            CredentialType::PhoneNumber => self.min_phone_number_length,
+            CredentialType::Email => self.min_email_length,
+            CredentialType::Username => self.min_username_length,
+        }
+    }
+}
+
+/// Application-level secret mixed into every password hash, identified by `key_id`,
+/// which gets stored next to each hash. Keep the secret out of the database.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PasswordPepper {
+    pub key_id: String,
+    pub secret: Vec<u8>,
+}
+
+impl Debug for PasswordPepper {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PasswordPepper")
+            .field("key_id", &self.key_id)
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Builds an `AuthConfig`, starting from the defaults.
+#[derive(Debug, Clone, Default)]
+pub struct AuthConfigBuilder {
+    config: AuthConfig,
+}
+
+impl AuthConfigBuilder {
+    pub fn auth_token_expiration_time_millis(mut self, millis: i64) -> Self {
+        self.config.auth_token_expiration_time_millis = millis;
+        self
+    }
+
+    pub fn refresh_token_expiration_time_millis(mut self, millis: i64) -> Self {
+        self.config.refresh_token_expiration_time_millis = millis;
+        self
+    }
+
+    pub fn max_session_age_millis(mut self, millis: i64) -> Self {
+        self.config.max_session_age_millis = millis;
+        self
+    }
+
+    pub fn email_length(mut self, min: usize, max: usize) -> Self {
+        self.config.min_email_length = min;
+        self.config.max_email_length = max;
+        self
+    }
+
+    pub fn phone_number_length(mut self, min: usize, max: usize) -> Self {
+        self.config.min_phone_number_length = min;
+        self.config.max_phone_number_length = max;
+        self
+    }
+
+    pub fn username_length(mut self, min: usize, max: usize) -> Self {
+        self.config.min_username_length = min;
+        self.config.max_username_length = max;
+        self
+    }
+
+    pub fn name_length(mut self, min: usize, max: usize) -> Self {
+        self.config.min_name_length = min;
+        self.config.max_name_length = max;
+        self
+    }
+
+    pub fn password_length(mut self, min: usize, max: usize) -> Self {
+        self.config.min_password_length = min;
+        self.config.max_password_length = max;
+        self
+    }
+
+    pub fn password_policy(mut self, password_policy: PasswordPolicy) -> Self {
+        self.config.password_policy = password_policy;
+        self
+    }
+
+    /// See `validation::breached_passwords` for a sorted hash list or a compact filter file.
+    pub fn breached_passwords(
+        mut self,
+        breached_passwords: impl BreachedPasswordList + 'static,
+    ) -> Self {
+        self.config.breached_passwords = Some(Arc::new(breached_passwords));
+        self
+    }
+
+    /// Accepts passwords as not breached when the breached password list can't be read, instead of failing the request.
+    pub fn breached_passwords_fail_open(mut self, fail_open: bool) -> Self {
+        self.config.breached_passwords_fail_open = fail_open;
+        self
+    }
+
+    /// Argon2id by default. See `utils::password_hasher` for scrypt, bcrypt and PBKDF2.
+    pub fn password_hasher(mut self, password_hasher: impl PasswordHasher + 'static) -> Self {
+        self.config.password_hasher = Arc::new(password_hasher);
+        self
+    }
+
+    /// Adds a pepper and makes it the one new hashes use. Keep adding the previous peppers first,
+    /// in the same order, so the hashes made with them keep verifying until they're re-peppered on login.
+    pub fn password_pepper(mut self, key_id: &str, secret: &[u8]) -> Self {
+        self.config
+            .password_peppers
+            .retain(|pepper| pepper.key_id != key_id);
+        self.config.password_peppers.push(PasswordPepper {
+            key_id: key_id.to_string(),
+            secret: secret.to_vec(),
+        });
+        self
+    }
+
+    /// Password hashes computed at the same time, `MAX_CONCURRENT_HASHES` by default.
+    /// At least 1, with 0 no password could ever be hashed.
+    pub fn max_concurrent_hashes(mut self, max_concurrent_hashes: usize) -> Self {
+        self.config.hashing_permits = Arc::new(Semaphore::new(max_concurrent_hashes.max(1)));
+        self
+    }
+
+    pub fn max_credentials_per_user(mut self, max_credentials_per_user: usize) -> Self {
+        self.config.max_credentials_per_user = max_credentials_per_user;
+        self
+    }
+
+    /// Refuses password logins through unverified credentials of `credential_type`.
+    /// Usernames can't be verified, requiring them would lock every username login out, so they're ignored.
+    pub fn require_verified_credential(mut self, credential_type: CredentialType) -> Self {
+        if credential_type == CredentialType::Username {
+            warn!("Usernames can't be verified, ignoring require_verified_credential(Username).");
+        } else if !self
+            .config
+            .require_verified_credentials
+            .contains(&credential_type)
+        {
+            self.config
+                .require_verified_credentials
+                .push(credential_type);
+        }
+        self
+    }
+
+    pub fn default_phone_region(mut self, region: &str) -> Self {
+        self.config.default_phone_region = Some(region.to_uppercase());
+        self
+    }
+
+    pub fn verification_code_expiration_time_millis(mut self, millis: i64) -> Self {
+        self.config.verification_code_expiration_time_millis = millis;
+        self
+    }
+
+    pub fn verification_code_length(mut self, verification_code_length: usize) -> Self {
+        self.config.verification_code_length = verification_code_length;
+        self
+    }
+
+    pub fn max_verification_attempts(mut self, max_verification_attempts: i32) -> Self {
+        self.config.max_verification_attempts = max_verification_attempts;
+        self
+    }
+
+    pub fn build(self) -> AuthConfig {
+        self.config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usernames_are_never_required_to_be_verified() {
+        let config = AuthConfig::builder()
+            .require_verified_credential(CredentialType::Username)
+            .require_verified_credential(CredentialType::Email)
+            .build();
+        assert_eq!(
+            config.require_verified_credentials,
+            vec![CredentialType::Email]
+        );
+        assert!(config.requires_verified_credential(&CredentialType::Email));
+        assert!(!config.requires_verified_credential(&CredentialType::Username));
+        assert!(!config.requires_verified_credential(&CredentialType::PhoneNumber));
+
+        let mut config = AuthConfig::default();
+        config
+            .require_verified_credentials
+            .push(CredentialType::Username);
+        assert!(!config.requires_verified_credential(&CredentialType::Username));
+    }
+}
@@ -0,0 +1,2 @@
+pub mod auth_config;
+pub mod password_policy;
@@ -0,0 +1,58 @@
+use crate::resources::password_policy::{
+    MAX_REPEATED_PASSWORD_CHARACTERS, MIN_PASSWORD_STRENGTH_SCORE, PASSWORD_HISTORY_DEPTH,
+};
+
+/// Rules every new password has to follow, on top of the length limits of `AuthConfig`.
+/// Checked when registering and when resetting or force resetting a password.
+/// Each broken rule is reported as its own `ErrorResource`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    /// Anything that isn't a letter or a digit counts as a symbol.
+    pub require_symbol: bool,
+    /// Longest run of the same character, like `aaaa`. `None` allows any.
+    pub max_repeated_characters: Option<usize>,
+    /// Minimum score of the strength estimator, from 0 (guessable in under a thousand tries)
+    /// to 4 (over ten billion). 0 disables the check.
+    pub min_strength_score: u8,
+    /// Rejects passwords that contain the user's name or any of their credentials.
+    pub reject_personal_information: bool,
+    /// Previous passwords kept per user in `password_history`. Neither them nor the current password
+    /// can be set again. Older ones are deleted. 0 disables the history.
+    pub history_depth: usize,
+    /// Whether `force_reset_password` also rejects recently used passwords. `reset_password` always does.
+    pub check_history_on_force_reset: bool,
+}
+
+impl Default for PasswordPolicy {
+    /// Only keeps the password history. Every password that passed the length limits before still does,
+    /// use `PasswordPolicy::recommended()` to opt into the stricter rules.
+    fn default() -> Self {
+        PasswordPolicy {
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            max_repeated_characters: None,
+            min_strength_score: 0,
+            reject_personal_information: false,
+            history_depth: PASSWORD_HISTORY_DEPTH,
+            check_history_on_force_reset: true,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Rejects long runs of the same character, weak passwords and passwords containing personal information.
+    /// Character classes are still optional, they add less than length does.
+    pub fn recommended() -> Self {
+        PasswordPolicy {
+            max_repeated_characters: Some(MAX_REPEATED_PASSWORD_CHARACTERS),
+            min_strength_score: MIN_PASSWORD_STRENGTH_SCORE,
+            reject_personal_information: true,
+            ..PasswordPolicy::default()
+        }
+    }
+}
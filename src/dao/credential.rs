@@ -1,4 +1,4 @@
-use crate::domain::credential::Credential;
+use crate::domain::credential::{Credential, CredentialType};
 use crate::dto::credential::CredentialDto;
 use chrono::Utc;
 use sqlx::{Error, PgConnection};
@@ -28,9 +28,78 @@ pub(crate) async fn fetch_user_credentials(
     sqlx::query_as(r#"SELECT user_id, credential_type, credential, validated, time_created, last_updated FROM "credential" WHERE user_id = $1 "#).bind(user_id).fetch_all(conn).await
 }
 
+/// Credentials are only unique per type, so the lookup needs both.
 pub(crate) async fn get_credential(
     conn: &mut PgConnection,
-    credential: String,
+    credential_type: &CredentialType,
+    credential: &str,
 ) -> Result<Option<Credential>, Error> {
-    sqlx::query_as(r#"SELECT user_id, credential_type, credential, validated, time_created, last_updated FROM "credential" WHERE credential = $1"#).bind(credential).fetch_optional(conn).await
+    sqlx::query_as(r#"SELECT user_id, credential_type, credential, validated, time_created, last_updated FROM "credential" WHERE credential_type = $1 AND credential = $2"#).bind(credential_type).bind(credential).fetch_optional(conn).await
+}
+
+/// Marks the user's credential of `credential_type` as validated, as long as it still holds `credential`.
+pub(crate) async fn set_credential_validated(
+    conn: &mut PgConnection,
+    user_id: &i32,
+    credential_type: &CredentialType,
+    credential: &str,
+) -> Result<Option<Credential>, Error> {
+    sqlx::query_as(r#"UPDATE "credential" SET validated = true, last_updated = $4 WHERE user_id = $1 AND credential_type = $2 AND credential = $3 RETURNING user_id, credential_type, credential, validated, time_created, last_updated"#)
+        .bind(user_id)
+        .bind(credential_type)
+        .bind(credential)
+        .bind(Utc::now())
+        .fetch_optional(conn)
+        .await
+}
+
+/// Changes the value of the user's credential of `credential_type`. A new value isn't verified, so `validated` is reset.
+pub(crate) async fn update_credential(
+    conn: &mut PgConnection,
+    user_id: &i32,
+    credential_type: &CredentialType,
+    credential: &str,
+) -> Result<Option<Credential>, Error> {
+    sqlx::query_as(r#"UPDATE "credential" SET credential = $3, validated = false, last_updated = $4 WHERE user_id = $1 AND credential_type = $2 RETURNING user_id, credential_type, credential, validated, time_created, last_updated"#)
+        .bind(user_id)
+        .bind(credential_type)
+        .bind(credential)
+        .bind(Utc::now())
+        .fetch_optional(conn)
+        .await
+}
+
+pub(crate) async fn delete_credential(
+    conn: &mut PgConnection,
+    user_id: &i32,
+    credential_type: &CredentialType,
+) -> Result<Option<Credential>, Error> {
+    sqlx::query_as(r#"DELETE FROM "credential" WHERE user_id = $1 AND credential_type = $2 RETURNING user_id, credential_type, credential, validated, time_created, last_updated"#)
+        .bind(user_id)
+        .bind(credential_type)
+        .fetch_optional(conn)
+        .await
+}
+
+pub(crate) async fn fetch_all_credentials(
+    conn: &mut PgConnection,
+) -> Result<Vec<Credential>, Error> {
+    sqlx::query_as(r#"SELECT user_id, credential_type, credential, validated, time_created, last_updated FROM "credential" ORDER BY user_id, credential_type"#).fetch_all(conn).await
+}
+
+/// Rewrites a credential's value in place. Unlike `update_credential` it keeps `validated`, so it's only for values
+/// that still mean the same thing, like the canonical form of the old one.
+pub(crate) async fn replace_credential_value(
+    conn: &mut PgConnection,
+    user_id: &i32,
+    credential_type: &CredentialType,
+    credential: &str,
+) -> Result<Option<Credential>, Error> {
+    sqlx::query_as(r#"UPDATE "credential" SET credential = $3, last_updated = $4 WHERE user_id = $1 AND credential_type = $2 RETURNING user_id, credential_type, credential, validated, time_created, last_updated"#)
+        .bind(user_id)
+        .bind(credential_type)
+        .bind(credential)
+        .bind(Utc::now())
+        .fetch_optional(conn)
+        .await
 }
@@ -0,0 +1,75 @@
+use crate::domain::credential::CredentialType;
+use crate::domain::credential_verification::CredentialVerification;
+use chrono::{DateTime, Utc};
+use sqlx::{Error, PgConnection};
+
+//  Codes are stored as salted hashes made by `hash_verification_code`, which is too slow to run inside a query.
+//  Compare codes against the returned rows with `verify_verification_code`.
+
+/// Stores a new code for the user's credential of `credential_type`, replacing any pending one
+/// and resetting its attempt counter.
+pub(crate) async fn upsert_credential_verification(
+    conn: &mut PgConnection,
+    user_id: &i32,
+    credential_type: &CredentialType,
+    credential: &str,
+    code_hash: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<CredentialVerification, Error> {
+    sqlx::query_as(
+        r#"INSERT INTO "credential_verification"
+    (user_id, credential_type, credential, code, attempts, time_created, expires_at)
+    VALUES ($1, $2, $3, $4, 0, $5, $6)
+    ON CONFLICT (user_id, credential_type) DO UPDATE SET
+    credential = $3, code = $4, attempts = 0, time_created = $5, expires_at = $6
+    RETURNING *;"#,
+    )
+    .bind(user_id)
+    .bind(credential_type)
+    .bind(credential)
+    .bind(code_hash)
+    .bind(Utc::now())
+    .bind(expires_at)
+    .fetch_one(conn)
+    .await
+}
+
+pub(crate) async fn get_credential_verification(
+    conn: &mut PgConnection,
+    user_id: &i32,
+    credential_type: &CredentialType,
+) -> Result<Option<CredentialVerification>, Error> {
+    sqlx::query_as(
+        r#"SELECT * FROM "credential_verification" WHERE user_id = $1 AND credential_type = $2;"#,
+    )
+    .bind(user_id)
+    .bind(credential_type)
+    .fetch_optional(conn)
+    .await
+}
+
+/// Counts one more attempt, as long as there are attempts left. Returns `None` once `max_attempts` were used.
+/// The row stays locked until the transaction ends, so concurrent attempts queue up instead of all reading the same count.
+pub(crate) async fn claim_credential_verification_attempt(
+    conn: &mut PgConnection,
+    verification_id: &i32,
+    max_attempts: i32,
+) -> Result<Option<CredentialVerification>, Error> {
+    sqlx::query_as(
+        r#"UPDATE "credential_verification" SET attempts = attempts + 1 WHERE id = $1 AND attempts < $2 RETURNING *;"#,
+    )
+    .bind(verification_id)
+    .bind(max_attempts)
+    .fetch_optional(conn)
+    .await
+}
+
+pub(crate) async fn delete_credential_verification(
+    conn: &mut PgConnection,
+    verification_id: &i32,
+) -> Result<Option<CredentialVerification>, Error> {
+    sqlx::query_as(r#"DELETE FROM "credential_verification" WHERE id = $1 RETURNING *;"#)
+        .bind(verification_id)
+        .fetch_optional(conn)
+        .await
+}
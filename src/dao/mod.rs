@@ -1,4 +1,6 @@
 pub mod credential;
+pub mod credential_verification;
+pub mod password_history;
 pub mod pg_queries;
 pub mod token;
 pub mod user;
@@ -0,0 +1,56 @@
+use crate::domain::password_history::PasswordHistoryEntry;
+use chrono::Utc;
+use sqlx::{Error, PgConnection};
+
+pub(crate) async fn insert_password_history(
+    conn: &mut PgConnection,
+    user_id: &i32,
+    password: &str,
+    pepper_key_id: Option<&str>,
+) -> Result<PasswordHistoryEntry, Error> {
+    sqlx::query_as(
+        r#"INSERT INTO "password_history" (user_id, password, pepper_key_id, time_created)
+    VALUES ($1, $2, $3, $4) RETURNING *;"#,
+    )
+    .bind(user_id)
+    .bind(password)
+    .bind(pepper_key_id)
+    .bind(Utc::now())
+    .fetch_one(conn)
+    .await
+}
+
+/// The user's `limit` most recent previous passwords, newest first.
+pub(crate) async fn fetch_password_history(
+    conn: &mut PgConnection,
+    user_id: &i32,
+    limit: i64,
+) -> Result<Vec<PasswordHistoryEntry>, Error> {
+    sqlx::query_as(
+        r#"SELECT * FROM "password_history" WHERE user_id = $1
+    ORDER BY time_created DESC, id DESC LIMIT $2;"#,
+    )
+    .bind(user_id)
+    .bind(limit)
+    .fetch_all(conn)
+    .await
+}
+
+/// Deletes all but the user's `depth` most recent previous passwords. Returns the amount deleted.
+pub(crate) async fn trim_password_history(
+    conn: &mut PgConnection,
+    user_id: &i32,
+    depth: i64,
+) -> Result<u64, Error> {
+    sqlx::query(
+        r#"DELETE FROM "password_history" WHERE user_id = $1 AND id NOT IN (
+        SELECT id FROM "password_history" WHERE user_id = $1
+        ORDER BY time_created DESC, id DESC LIMIT $2
+    );"#,
+    )
+    .bind(user_id)
+    .bind(depth)
+    .execute(conn)
+    .await
+    .map(|result| result.rows_affected())
+}
@@ -1,50 +1,145 @@
-use crate::domain::token::Token;
-use chrono::Utc;
+use crate::domain::token::{RetiredRefreshToken, Session, Token};
+use crate::utils::hasher::{hash_token, verify_token};
+use chrono::{DateTime, Utc};
 use sqlx::{Error, PgConnection};
 
+//  Tokens are only ever stored as SHA-256 digests. Every function here takes the plaintext
+//  tokens and hashes them before they reach the database, so the rows that come back hold digests.
+//  Lookups by a client-supplied token match the digest in SQL, through the indexes of `111_token_indexes.sql`,
+//  and compare the row they find once more in constant time with `verify_token`.
+
 pub(crate) async fn insert_token(conn: &mut PgConnection, token: Token) -> Result<Token, Error> {
     sqlx::query_as(r#"INSERT INTO token (
     user_id, auth_token, refresh_token, time_created, last_updated) VALUES ($1, $2, $3, $4, $4) RETURNING *;"#)
-        .bind(token.user_id).bind(token.auth_token).bind(token.refresh_token).bind(token.time_created)
+        .bind(token.user_id).bind(hash_token(&token.auth_token)).bind(hash_token(&token.refresh_token)).bind(token.time_created)
         .fetch_one(conn).await
 }
 
+/// Swaps both tokens of `persisted_token`'s session and retires its refresh token in the same statement.
+/// The session is only rotated if it still holds the refresh token it was read with, its refresh token
+/// was issued after `refresh_token_issued_after` and it was created after `session_created_after`.
+/// Returns `None` otherwise.
 pub(crate) async fn update_token(
     conn: &mut PgConnection,
-    refresh_token: String,
+    persisted_token: &Token,
     new_auth_token: String,
-) -> Result<Token, Error> {
+    new_refresh_token: String,
+    refresh_token_issued_after: DateTime<Utc>,
+    session_created_after: DateTime<Utc>,
+) -> Result<Option<Token>, Error> {
+    //  `persisted_token` comes from the database, so its refresh token is already a digest.
     sqlx::query_as(
-        r#"UPDATE token set
-    auth_token = $3, last_updated = $4
-    WHERE refresh_token = $1 RETURNING *;"#,
+        r#"WITH rotated AS (
+        UPDATE token SET
+        auth_token = $2, refresh_token = $3, last_updated = $4
+        WHERE id = $1 AND refresh_token = $5 AND last_updated > $6 AND time_created > $7
+        RETURNING *
+    ), retired AS (
+        INSERT INTO retired_refresh_token (refresh_token, token_id, user_id, time_retired)
+        SELECT $5, id, user_id, $4 FROM rotated
     )
-    .bind(refresh_token)
-    .bind(new_auth_token)
+    SELECT * FROM rotated;"#,
+    )
+    .bind(persisted_token.id)
+    .bind(hash_token(&new_auth_token))
+    .bind(hash_token(&new_refresh_token))
     .bind(Utc::now())
-    .fetch_one(conn)
+    .bind(&persisted_token.refresh_token)
+    .bind(refresh_token_issued_after)
+    .bind(session_created_after)
+    .fetch_optional(conn)
+    .await
+}
+
+pub(crate) async fn get_token_with_refresh_token(
+    conn: &mut PgConnection,
+    user_id: &i32,
+    refresh_token: String,
+) -> Result<Option<Token>, Error> {
+    let token: Option<Token> =
+        sqlx::query_as(r#"SELECT * FROM token WHERE user_id = $1 AND refresh_token = $2;"#)
+            .bind(user_id)
+            .bind(hash_token(&refresh_token))
+            .fetch_optional(conn)
+            .await?;
+    Ok(token.filter(|token| verify_token(&refresh_token, &token.refresh_token)))
+}
+
+pub(crate) async fn get_retired_refresh_token(
+    conn: &mut PgConnection,
+    user_id: &i32,
+    refresh_token: String,
+) -> Result<Option<RetiredRefreshToken>, Error> {
+    let retired_token: Option<RetiredRefreshToken> = sqlx::query_as(
+        r#"SELECT * FROM retired_refresh_token WHERE user_id = $1 AND refresh_token = $2;"#,
+    )
+    .bind(user_id)
+    .bind(hash_token(&refresh_token))
+    .fetch_optional(conn)
+    .await?;
+    Ok(retired_token
+        .filter(|retired_token| verify_token(&refresh_token, &retired_token.refresh_token)))
+}
+
+pub(crate) async fn get_user_sessions(
+    conn: &mut PgConnection,
+    user_id: &i32,
+) -> Result<Vec<Session>, Error> {
+    sqlx::query_as(
+        r#"SELECT id, user_id, time_created, last_updated FROM token WHERE user_id = $1 ORDER BY last_updated DESC;"#,
+    )
+    .bind(user_id)
+    .fetch_all(conn)
     .await
 }
 
-#[allow(unused)]
 pub(crate) async fn remove_token(
     conn: &mut PgConnection,
+    user_id: &i32,
     token_id: &i32,
 ) -> Result<Option<Token>, Error> {
-    sqlx::query_as(r#"DELETE FROM token WHERE id = $1 RETURNING *;"#)
+    sqlx::query_as(r#"DELETE FROM token WHERE id = $1 AND user_id = $2 RETURNING *;"#)
         .bind(token_id)
+        .bind(user_id)
         .fetch_optional(conn)
         .await
 }
 
+pub(crate) async fn remove_all_user_tokens(
+    conn: &mut PgConnection,
+    user_id: &i32,
+) -> Result<Vec<Token>, Error> {
+    sqlx::query_as(r#"DELETE FROM token WHERE user_id = $1 RETURNING *;"#)
+        .bind(user_id)
+        .fetch_all(conn)
+        .await
+}
+
 pub(crate) async fn validate_user_token(
     conn: &mut PgConnection,
     user_id: &i32,
     auth_token: String,
 ) -> Result<Option<Token>, Error> {
-    sqlx::query_as(r#"SELECT * FROM token where user_id = $1 AND auth_token = $2;"#)
-        .bind(user_id)
-        .bind(auth_token)
-        .fetch_optional(conn)
+    let token: Option<Token> =
+        sqlx::query_as(r#"SELECT * FROM token WHERE user_id = $1 AND auth_token = $2;"#)
+            .bind(user_id)
+            .bind(hash_token(&auth_token))
+            .fetch_optional(conn)
+            .await?;
+    Ok(token.filter(|token| verify_token(&auth_token, &token.auth_token)))
+}
+
+/// Deletes every session whose tokens weren't used or rotated since `last_updated_before`
+/// or that was created before `created_before`. Returns the amount of deleted sessions.
+pub(crate) async fn delete_expired_tokens(
+    conn: &mut PgConnection,
+    last_updated_before: DateTime<Utc>,
+    created_before: DateTime<Utc>,
+) -> Result<u64, Error> {
+    sqlx::query(r#"DELETE FROM token WHERE last_updated < $1 OR time_created < $2;"#)
+        .bind(last_updated_before)
+        .bind(created_before)
+        .execute(conn)
         .await
+        .map(|result| result.rows_affected())
 }
@@ -4,13 +4,13 @@ use sqlx::PgConnection;
 pub(crate) async fn insert_user(conn: &mut PgConnection, user: User) -> Result<User, sqlx::Error> {
     sqlx::query_as(
         r#"
-    INSERT INTO "user" (name, password, salt, time_created, last_updated)
+    INSERT INTO "user" (name, password, pepper_key_id, time_created, last_updated)
     VALUES ($1, $2, $3, $4, $4) RETURNING *;
     "#,
     )
     .bind(user.name)
     .bind(user.password)
-    .bind(user.salt)
+    .bind(user.pepper_key_id)
     .bind(user.time_created)
     .fetch_one(conn)
     .await
@@ -34,14 +34,14 @@ pub(crate) async fn update_user(conn: &mut PgConnection, user: User) -> Result<U
     sqlx::query_as(
         r#"
     UPDATE "user" SET
-    name = $2, password = $3, salt = $4, last_updated = $5
+    name = $2, password = $3, pepper_key_id = $4, last_updated = $5
     WHERE id = $1 RETURNING *;
     "#,
     )
     .bind(user.id)
     .bind(user.name)
     .bind(user.password)
-    .bind(user.salt)
+    .bind(user.pepper_key_id)
     .bind(user.last_updated)
     .fetch_one(conn)
     .await
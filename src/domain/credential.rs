@@ -30,6 +30,16 @@ pub struct Credential {
     pub last_updated: DateTime<Utc>,
 }
 
+/// Stored credentials of the same type that become the same value once canonicalized, found by `find_credential_collisions`.
+/// They can't all keep it, so someone has to decide which user does before `canonicalize_persisted_credentials` touches them.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialCollision {
+    pub credential_type: CredentialType,
+    pub canonical_credential: String,
+    pub credentials: Vec<Credential>,
+}
+
 impl CredentialType {
     pub fn get_max_length(&self) -> usize {
         match self {
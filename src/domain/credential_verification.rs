@@ -0,0 +1,23 @@
+use crate::domain::credential::CredentialType;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// A pending verification of a credential. `code` only holds the salted hash made by `hash_verification_code`, never the code itself.
+/// There's at most one per user and credential type; issuing a new code replaces the previous one.
+#[derive(
+    FromRow, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialVerification {
+    #[serde(skip_serializing, skip_deserializing)]
+    pub id: i32,
+    pub user_id: i32,
+    pub credential_type: CredentialType,
+    pub credential: String,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub code: String,
+    pub attempts: i32,
+    pub time_created: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
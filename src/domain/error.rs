@@ -1,3 +1,8 @@
+use crate::domain::credential::CredentialType;
+use crate::dto::problem_details::ProblemDetails;
+use crate::resources::error_messages::{
+    get_http_status_for_all, ErrorResource, ERROR_CREDENTIAL_NOT_VERIFIED, ERROR_DATABASE,
+};
 use std::fmt::Display;
 
 /// Used to return a simple error from FromStr implementations
@@ -11,3 +16,191 @@ impl Display for FromStrError {
 }
 
 impl std::error::Error for FromStrError {}
+
+/// Returned by a `PasswordHasher` when a hash can't be computed, or a stored hash can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordHashError(pub String);
+
+impl Display for PasswordHashError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Error hashing password. {}", self.0)
+    }
+}
+
+impl std::error::Error for PasswordHashError {}
+
+impl From<argon2::password_hash::Error> for PasswordHashError {
+    fn from(error: argon2::password_hash::Error) -> Self {
+        PasswordHashError(error.to_string())
+    }
+}
+
+impl From<argon2::Error> for PasswordHashError {
+    fn from(error: argon2::Error) -> Self {
+        PasswordHashError(error.to_string())
+    }
+}
+
+impl From<bcrypt::BcryptError> for PasswordHashError {
+    fn from(error: bcrypt::BcryptError) -> Self {
+        PasswordHashError(error.to_string())
+    }
+}
+
+/// Returned by a `NotificationSender` when a notification couldn't be delivered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotificationError(pub String);
+
+impl Display for NotificationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Error sending notification. {}", self.0)
+    }
+}
+
+impl std::error::Error for NotificationError {}
+
+/// Returned when new session tokens can't be generated.
+#[derive(Debug)]
+pub enum TokenGenerationError {
+    /// The system's random number generator failed.
+    Random,
+    /// A token generating task panicked or was cancelled.
+    Join(tokio::task::JoinError),
+    /// A generated token came out empty.
+    Empty,
+}
+
+impl Display for TokenGenerationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenGenerationError::Random => write!(f, "Failed to generate random token."),
+            TokenGenerationError::Join(error) => {
+                write!(f, "Token generation task failed. {}", error)
+            }
+            TokenGenerationError::Empty => write!(f, "Generated token is empty."),
+        }
+    }
+}
+
+impl std::error::Error for TokenGenerationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TokenGenerationError::Join(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Error returned by every service function.
+/// Use `to_error_resources()` (or `Vec::from`) to get the `ErrorResource` key/message pairs for your responses.
+#[derive(Debug)]
+pub enum UserLibError {
+    /// A field of the payload is invalid.
+    Validation(ErrorResource<'static>),
+    /// The payload collides with something that already exists, like a credential used by another user.
+    Conflict(ErrorResource<'static>),
+    /// The user or credential doesn't exist, or the password is wrong.
+    Authentication(ErrorResource<'static>),
+    /// The supplied token or verification code is missing, incorrect, expired or was already used.
+    Token(ErrorResource<'static>),
+    /// The credential used to log in isn't verified and `AuthConfig::require_verified_credentials` asks for it.
+    /// Holds what `send_credential_verification` needs to send a new code.
+    CredentialNotVerified {
+        user_id: i32,
+        credential_type: CredentialType,
+    },
+    /// The database returned an error.
+    Storage(sqlx::Error),
+    /// Something failed inside the library, like generating tokens.
+    Internal {
+        resource: ErrorResource<'static>,
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
+    },
+    /// Several errors found at once, in the order they were found.
+    Multiple(Vec<UserLibError>),
+}
+
+impl UserLibError {
+    /// Turns a list of errors into a single one. A list with only one error returns that error.
+    pub(crate) fn from_errors(mut errors: Vec<UserLibError>) -> UserLibError {
+        if errors.len() == 1 {
+            errors.remove(0)
+        } else {
+            UserLibError::Multiple(errors)
+        }
+    }
+
+    pub fn to_error_resources(&self) -> Vec<ErrorResource<'static>> {
+        match self {
+            UserLibError::Validation(resource)
+            | UserLibError::Conflict(resource)
+            | UserLibError::Authentication(resource)
+            | UserLibError::Token(resource)
+            | UserLibError::Internal { resource, .. } => vec![*resource],
+            UserLibError::CredentialNotVerified { .. } => vec![ERROR_CREDENTIAL_NOT_VERIFIED],
+            UserLibError::Storage(_) => vec![ERROR_DATABASE],
+            UserLibError::Multiple(errors) => errors
+                .iter()
+                .flat_map(UserLibError::to_error_resources)
+                .collect(),
+        }
+    }
+
+    /// Recommended HTTP status code to respond with.
+    pub fn http_status(&self) -> u16 {
+        get_http_status_for_all(&self.to_error_resources())
+    }
+
+    /// RFC 7807 body to respond with, see `ProblemDetails::to_json`.
+    pub fn to_problem_details(&self) -> ProblemDetails {
+        ProblemDetails::from(self)
+    }
+}
+
+impl Display for UserLibError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UserLibError::Storage(error) => write!(f, "{}: {}", ERROR_DATABASE.0, error),
+            UserLibError::Multiple(errors) => {
+                for (index, error) in errors.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", error)?;
+                }
+                Ok(())
+            }
+            _ => {
+                for (key, message) in self.to_error_resources() {
+                    write!(f, "{}: {}", key, message)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for UserLibError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            UserLibError::Storage(error) => Some(error),
+            UserLibError::Internal {
+                source: Some(source),
+                ..
+            } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for UserLibError {
+    fn from(error: sqlx::Error) -> Self {
+        UserLibError::Storage(error)
+    }
+}
+
+impl From<UserLibError> for Vec<ErrorResource<'static>> {
+    fn from(error: UserLibError) -> Self {
+        error.to_error_resources()
+    }
+}
@@ -1,5 +1,7 @@
 pub mod credential;
+pub mod credential_verification;
 pub mod error;
 pub mod impls;
+pub mod password_history;
 pub mod token;
 pub mod user;
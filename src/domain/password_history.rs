@@ -0,0 +1,19 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// A password hash the user had before, kept to stop them from setting it again.
+#[derive(
+    FromRow, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordHistoryEntry {
+    #[serde(skip_serializing, skip_deserializing)]
+    pub id: i32,
+    pub user_id: i32,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub password: String,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub pepper_key_id: Option<String>,
+    pub time_created: DateTime<Utc>,
+}
@@ -2,11 +2,13 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
+/// A session. The database only stores SHA-256 digests of `auth_token` and `refresh_token`;
+/// the plaintext tokens are only ever returned once, when they are created or rotated.
 #[derive(
     FromRow, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord,
 )]
 pub struct Token {
-    #[serde(skip_serializing, skip_deserializing)]
+    #[serde(default)]
     pub id: i32,
     #[serde(rename = "userId")]
     pub user_id: i32,
@@ -19,3 +21,29 @@ pub struct Token {
     #[serde(rename = "lastUpdated")]
     pub last_updated: DateTime<Utc>,
 }
+
+/// A session as it's listed to its user, without any token digests.
+/// `id` is the one `revoke_session` takes.
+#[derive(
+    FromRow, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    pub id: i32,
+    pub user_id: i32,
+    pub time_created: DateTime<Utc>,
+    pub last_updated: DateTime<Utc>,
+}
+
+/// A refresh token that was already exchanged for a new pair of tokens.
+/// `token_id` points to the session (token family) it was issued for.
+#[derive(
+    FromRow, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct RetiredRefreshToken {
+    pub refresh_token: String,
+    pub token_id: i32,
+    pub user_id: i32,
+    pub time_retired: DateTime<Utc>,
+}
@@ -10,8 +10,9 @@ pub struct User {
     pub name: String,
     #[serde(skip_serializing, skip_deserializing)]
     pub password: String,
+    /// Key id of the pepper the password was hashed with, `None` if it wasn't peppered.
     #[serde(skip_serializing, skip_deserializing)]
-    pub salt: String,
+    pub pepper_key_id: Option<String>,
     #[serde(rename = "timeCreated")]
     pub time_created: DateTime<Utc>,
     #[serde(rename = "lastUpdated")]
@@ -1,4 +1,5 @@
 pub mod credential;
-pub mod hash_result;
+pub mod problem_details;
 pub mod token;
 pub mod users;
+pub mod verification;
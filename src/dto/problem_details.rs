@@ -0,0 +1,112 @@
+use crate::config::auth_config::AuthConfig;
+use crate::domain::credential::CredentialType;
+use crate::domain::error::UserLibError;
+use crate::resources::error_messages::{get_http_status_for_all, ErrorResource};
+use crate::resources::message_catalog::MessageCatalog;
+use serde::{Deserialize, Serialize};
+
+/// Content type to send a `ProblemDetails` body with.
+pub const PROBLEM_JSON_CONTENT_TYPE: &str = "application/problem+json";
+
+/// RFC 7807 problem details body. The `ERROR.*` keys go in the `errors` extension member.
+/// `credentialType` is only present on `ERROR.CREDENTIAL_NOT_VERIFIED`, so the client knows which credential to ask a new code for.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub errors: Vec<ProblemError>,
+    #[serde(
+        rename = "credentialType",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub credential_type: Option<CredentialType>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProblemError {
+    pub key: String,
+    pub message: String,
+}
+
+impl ProblemDetails {
+    pub fn from_error_resources(error_resources: &[ErrorResource]) -> ProblemDetails {
+        let status = get_http_status_for_all(error_resources);
+        ProblemDetails {
+            problem_type: String::from("about:blank"),
+            title: get_status_title(status).to_string(),
+            status,
+            detail: error_resources
+                .iter()
+                .map(|(_, message)| *message)
+                .filter(|message| !message.is_empty())
+                .collect::<Vec<&str>>()
+                .join(" "),
+            errors: error_resources
+                .iter()
+                .map(|(key, message)| ProblemError {
+                    key: key.to_string(),
+                    message: message.to_string(),
+                })
+                .collect(),
+            credential_type: None,
+        }
+    }
+
+    /// Same body with every message (and the detail) translated to the best locale for `accept_language`.
+    pub fn localize(
+        mut self,
+        catalog: &MessageCatalog,
+        accept_language: &str,
+        config: &AuthConfig,
+    ) -> ProblemDetails {
+        for problem_error in self.errors.iter_mut() {
+            problem_error.message = catalog.localize(
+                &(problem_error.key.as_str(), problem_error.message.as_str()),
+                accept_language,
+                config,
+            );
+        }
+        self.detail = self
+            .errors
+            .iter()
+            .map(|problem_error| problem_error.message.as_str())
+            .filter(|message| !message.is_empty())
+            .collect::<Vec<&str>>()
+            .join(" ");
+        self
+    }
+
+    /// Renders the `application/problem+json` body.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+}
+
+impl From<&UserLibError> for ProblemDetails {
+    fn from(error: &UserLibError) -> Self {
+        let mut problem_details = ProblemDetails::from_error_resources(&error.to_error_resources());
+        if let UserLibError::CredentialNotVerified {
+            credential_type, ..
+        } = error
+        {
+            problem_details.credential_type = Some(credential_type.clone());
+        }
+        problem_details
+    }
+}
+
+fn get_status_title(status: u16) -> &'static str {
+    match status {
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        409 => "Conflict",
+        429 => "Too Many Requests",
+        _ => "Internal Server Error",
+    }
+}
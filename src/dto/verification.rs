@@ -0,0 +1,11 @@
+use crate::domain::credential::CredentialType;
+use serde::{Deserialize, Serialize};
+
+/// Used to confirm a credential with the code (or link token) that was sent to it.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfirmCredentialVerificationDto {
+    pub user_id: i32,
+    pub credential_type: CredentialType,
+    pub code: String,
+}
@@ -1,6 +1,8 @@
+pub mod config;
 pub mod dao;
 pub mod domain;
 pub mod dto;
+pub mod notification;
 pub mod resources;
 pub mod service;
 pub mod utils;
@@ -0,0 +1,69 @@
+use crate::domain::credential::CredentialType;
+use crate::domain::error::NotificationError;
+use chrono::{DateTime, Utc};
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+pub type NotificationFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<(), NotificationError>> + Send + 'a>>;
+
+/// A verification code to deliver to a credential, by email or SMS.
+/// For emails `code` is a URL-safe token meant to go in a link, for phone numbers it's a short numeric code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerificationNotification {
+    pub user_id: i32,
+    pub credential_type: CredentialType,
+    pub credential: String,
+    pub code: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Delivers verification codes. Implement it with your email and SMS providers.
+pub trait NotificationSender: Debug + Send + Sync {
+    fn send_verification(&self, notification: VerificationNotification) -> NotificationFuture<'_>;
+}
+
+/// Keeps every notification in memory instead of sending it. Meant for tests and local development.
+#[derive(Debug, Default)]
+pub struct InMemoryNotificationSender {
+    sent: Mutex<Vec<VerificationNotification>>,
+}
+
+impl InMemoryNotificationSender {
+    pub fn new() -> Self {
+        InMemoryNotificationSender::default()
+    }
+
+    /// Every notification sent so far, oldest first.
+    pub fn get_sent(&self) -> Vec<VerificationNotification> {
+        match self.sent.lock() {
+            Ok(sent) => sent.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Code of the latest notification sent to `credential`.
+    pub fn get_last_code(&self, credential: &str) -> Option<String> {
+        self.get_sent()
+            .into_iter()
+            .rev()
+            .find(|notification| notification.credential == credential)
+            .map(|notification| notification.code)
+    }
+}
+
+impl NotificationSender for InMemoryNotificationSender {
+    fn send_verification(&self, notification: VerificationNotification) -> NotificationFuture<'_> {
+        Box::pin(async move {
+            match self.sent.lock() {
+                Ok(mut sent) => {
+                    sent.push(notification);
+                    Ok(())
+                }
+                Err(e) => Err(NotificationError(e.to_string())),
+            }
+        })
+    }
+}
@@ -9,10 +9,13 @@ pub const ERROR_INVALID_EMAIL: (&str, &str) = (
 
 pub const ERROR_INVALID_PHONE_NUMBER: (&str, &str) = (
     "ERROR.INVALID_PHONE_NUMBER",
-    "Invalid Phone number. Needs to be 10 characters.",
+    "Invalid phone number. Needs to be at least 8 characters and at most 16.",
 );
 
-pub const ERROR_INVALID_USERNAME: (&str, &str) = ("ERROR.INVALID_USERNAME", "Invalid Username. ");
+pub const ERROR_INVALID_USERNAME: (&str, &str) = (
+    "ERROR.INVALID_USERNAME",
+    "Invalid username. Needs to be at least 3 characters and at most 64.",
+);
 
 pub const ERROR_INVALID_NAME: (&str, &str) = (
     "ERROR.INVALID_NAME",
@@ -24,15 +27,58 @@ pub const ERROR_INVALID_PASSWORD: (&str, &str) = (
     "Invalid password. Password should have at least 8 characters and at most 128.",
 );
 
+pub const ERROR_PASSWORD_MISSING_LOWERCASE: (&str, &str) = (
+    "ERROR.PASSWORD_MISSING_LOWERCASE",
+    "Password should have at least one lowercase letter.",
+);
+
+pub const ERROR_PASSWORD_MISSING_UPPERCASE: (&str, &str) = (
+    "ERROR.PASSWORD_MISSING_UPPERCASE",
+    "Password should have at least one uppercase letter.",
+);
+
+pub const ERROR_PASSWORD_MISSING_DIGIT: (&str, &str) = (
+    "ERROR.PASSWORD_MISSING_DIGIT",
+    "Password should have at least one digit.",
+);
+
+pub const ERROR_PASSWORD_MISSING_SYMBOL: (&str, &str) = (
+    "ERROR.PASSWORD_MISSING_SYMBOL",
+    "Password should have at least one symbol.",
+);
+
+pub const ERROR_PASSWORD_TOO_MANY_REPEATED_CHARACTERS: (&str, &str) = (
+    "ERROR.PASSWORD_TOO_MANY_REPEATED_CHARACTERS",
+    "Password can't repeat the same character more than 3 times in a row.",
+);
+
+pub const ERROR_PASSWORD_TOO_WEAK: (&str, &str) = (
+    "ERROR.PASSWORD_TOO_WEAK",
+    "Password is too easy to guess. Avoid common words, sequences and repeated characters.",
+);
+
+pub const ERROR_PASSWORD_CONTAINS_PERSONAL_INFORMATION: (&str, &str) = (
+    "ERROR.PASSWORD_CONTAINS_PERSONAL_INFORMATION",
+    "Password can't contain your name or any of your credentials.",
+);
+
+pub const ERROR_PASSWORD_BREACHED: (&str, &str) = (
+    "ERROR.PASSWORD_BREACHED",
+    "This password has appeared in a data breach. Please choose another one.",
+);
+
+pub const ERROR_PASSWORD_RECENTLY_USED: (&str, &str) = (
+    "ERROR.PASSWORD_RECENTLY_USED",
+    "You have used this password recently. Choose one that's different from your current password and your last 5.",
+);
+
 pub const ERROR_USER_ALREADY_EXISTS: (&str, &str) = (
     "ERROR.USER_ALREADY_EXISTS",
     "A user with that credential already exists.",
 );
 
-pub const ERROR_USER_DOES_NOT_EXIST: (&str, &str) = (
-    "ERROR.USER_DOES_NOT_EXIST",
-    "User with this email does not exist.",
-);
+pub const ERROR_USER_DOES_NOT_EXIST: (&str, &str) =
+    ("ERROR.USER_DOES_NOT_EXIST", "User does not exist.");
 
 pub const ERROR_PASSWORD_INCORRECT: (&str, &str) = (
     "ERROR.PASSWORD_INCORRECT",
@@ -56,6 +102,21 @@ pub const ERROR_EXPIRED_TOKEN: (&str, &str) = (
     "The token you have supplied is expired.",
 );
 
+pub const ERROR_EXPIRED_REFRESH_TOKEN: (&str, &str) = (
+    "ERROR.EXPIRED_REFRESH_TOKEN",
+    "The refresh token you have supplied is expired. Log in with your password again.",
+);
+
+pub const ERROR_EXPIRED_SESSION: (&str, &str) = (
+    "ERROR.EXPIRED_SESSION",
+    "The session you are trying to refresh is too old. Log in with your password again.",
+);
+
+pub const ERROR_REUSED_REFRESH_TOKEN: (&str, &str) = (
+    "ERROR.REUSED_REFRESH_TOKEN",
+    "The refresh token you have supplied was already used. The session it belongs to has been revoked.",
+);
+
 pub const ERROR_CREATING_TOKEN: (&str, &str) = (
     "ERROR.CREATING_TOKEN",
     "The server had an error creating the auth tokens.",
@@ -71,7 +132,170 @@ pub const ERROR_CREDENTIAL_DOES_NOT_EXIST: (&str, &str) = (
     "Credential specified does not exist.",
 );
 
+pub const ERROR_CREDENTIAL_TYPE_ALREADY_EXISTS: (&str, &str) = (
+    "ERROR.CREDENTIAL_TYPE_ALREADY_EXISTS",
+    "You already have a credential of this type. Change it instead.",
+);
+
+pub const ERROR_CANNOT_REMOVE_LAST_CREDENTIAL: (&str, &str) = (
+    "ERROR.CANNOT_REMOVE_LAST_CREDENTIAL",
+    "You can't remove your only credential. Add another one first.",
+);
+
+pub const ERROR_CREDENTIAL_NOT_VERIFIED: (&str, &str) = (
+    "ERROR.CREDENTIAL_NOT_VERIFIED",
+    "This credential isn't verified yet. Verify it with the code we sent you, or request a new one.",
+);
+
+pub const ERROR_CREDENTIAL_NOT_VERIFIABLE: (&str, &str) = (
+    "ERROR.CREDENTIAL_NOT_VERIFIABLE",
+    "Only emails and phone numbers can be verified.",
+);
+
+pub const ERROR_INVALID_VERIFICATION_CODE: (&str, &str) = (
+    "ERROR.INVALID_VERIFICATION_CODE",
+    "The verification code you have supplied is incorrect.",
+);
+
+pub const ERROR_EXPIRED_VERIFICATION_CODE: (&str, &str) = (
+    "ERROR.EXPIRED_VERIFICATION_CODE",
+    "The verification code you have supplied is expired. Request a new one.",
+);
+
+pub const ERROR_TOO_MANY_VERIFICATION_ATTEMPTS: (&str, &str) = (
+    "ERROR.TOO_MANY_VERIFICATION_ATTEMPTS",
+    "Too many incorrect verification codes. Request a new one.",
+);
+
+pub const ERROR_SENDING_NOTIFICATION: (&str, &str) = (
+    "ERROR.SENDING_NOTIFICATION",
+    "The server had an error sending the verification code.",
+);
+
+pub const ERROR_HASHING_PASSWORD: (&str, &str) = (
+    "ERROR.HASHING_PASSWORD",
+    "The server had an error hashing the password.",
+);
+
+pub const ERROR_HASHING_VERIFICATION_CODE: (&str, &str) = (
+    "ERROR.HASHING_VERIFICATION_CODE",
+    "The server had an error hashing the verification code.",
+);
+
+pub const ERROR_CHECKING_BREACHED_PASSWORDS: (&str, &str) = (
+    "ERROR.CHECKING_BREACHED_PASSWORDS",
+    "The server had an error checking the password against the breached password list.",
+);
+
+pub const ERROR_DATABASE: (&str, &str) = ("ERROR.DATABASE_ERROR", "");
+
+pub const ERROR_CRITICAL_USER_MISSING: (&str, &str) = (
+    "ERROR.DATABASE_ERROR",
+    "Critical. User doesn't exist but credentials pointing to the user do.",
+);
+
+pub const ERROR_JOIN: (&str, &str) = ("ERROR.JOIN_ERROR", "");
+
 pub const ERROR_TOKEN_NOT_CREATED: (&str, &str) = (
     "ERROR.TOKEN_NOT_CREATED",
     "Token futures were joined but not created correctly.",
 );
+
+/// Recommended HTTP status code for an error, looked up by its key.
+/// Keys this library doesn't know about are treated as server errors.
+pub fn get_http_status(error_resource: &ErrorResource) -> u16 {
+    match error_resource.0 {
+        "ERROR.INVALID_EMAIL"
+        | "ERROR.INVALID_PHONE_NUMBER"
+        | "ERROR.INVALID_USERNAME"
+        | "ERROR.INVALID_NAME"
+        | "ERROR.INVALID_PASSWORD"
+        | "ERROR.INVALID_TOKEN"
+        | "ERROR.PASSWORD_MISSING_LOWERCASE"
+        | "ERROR.PASSWORD_MISSING_UPPERCASE"
+        | "ERROR.PASSWORD_MISSING_DIGIT"
+        | "ERROR.PASSWORD_MISSING_SYMBOL"
+        | "ERROR.PASSWORD_TOO_MANY_REPEATED_CHARACTERS"
+        | "ERROR.PASSWORD_TOO_WEAK"
+        | "ERROR.PASSWORD_CONTAINS_PERSONAL_INFORMATION"
+        | "ERROR.PASSWORD_BREACHED"
+        | "ERROR.PASSWORD_RECENTLY_USED"
+        | "ERROR.TOO_MANY_CREDENTIALS"
+        | "ERROR.CREDENTIAL_NOT_VERIFIABLE"
+        | "ERROR.INVALID_VERIFICATION_CODE"
+        | "ERROR.EXPIRED_VERIFICATION_CODE" => 400,
+        "ERROR.PASSWORD_INCORRECT"
+        | "ERROR.INCORRECT_TOKEN"
+        | "ERROR.MISSING_TOKEN"
+        | "ERROR.EXPIRED_TOKEN"
+        | "ERROR.EXPIRED_REFRESH_TOKEN"
+        | "ERROR.EXPIRED_SESSION"
+        | "ERROR.REUSED_REFRESH_TOKEN" => 401,
+        "ERROR.USER_DOES_NOT_EXIST" | "ERROR.CREDENTIAL_DOES_NOT_EXIST" => 404,
+        "ERROR.CREDENTIAL_NOT_VERIFIED" => 403,
+        "ERROR.USER_ALREADY_EXISTS"
+        | "ERROR.CREDENTIAL_TYPE_ALREADY_EXISTS"
+        | "ERROR.CANNOT_REMOVE_LAST_CREDENTIAL" => 409,
+        "ERROR.TOO_MANY_VERIFICATION_ATTEMPTS" => 429,
+        _ => 500,
+    }
+}
+
+/// Recommended HTTP status code for a response holding several errors.
+/// Server errors win, then a status shared by every error. Mixed client errors become a 400.
+pub fn get_http_status_for_all(error_resources: &[ErrorResource]) -> u16 {
+    let statuses: Vec<u16> = error_resources.iter().map(get_http_status).collect();
+    match statuses.iter().max() {
+        None => 500,
+        Some(max_status) if *max_status >= 500 => *max_status,
+        Some(max_status) if statuses.iter().all(|status| status == max_status) => *max_status,
+        Some(_) => 400,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_errors_win() {
+        assert_eq!(
+            get_http_status_for_all(&[
+                ERROR_INVALID_EMAIL,
+                ERROR_DATABASE,
+                ERROR_PASSWORD_INCORRECT
+            ]),
+            500
+        );
+    }
+
+    #[test]
+    fn a_shared_status_is_kept() {
+        assert_eq!(
+            get_http_status_for_all(&[ERROR_EXPIRED_TOKEN, ERROR_REUSED_REFRESH_TOKEN]),
+            401
+        );
+        assert_eq!(
+            get_http_status_for_all(&[ERROR_INVALID_EMAIL, ERROR_INVALID_PASSWORD]),
+            400
+        );
+        assert_eq!(get_http_status_for_all(&[ERROR_USER_ALREADY_EXISTS]), 409);
+    }
+
+    #[test]
+    fn mixed_client_errors_become_a_bad_request() {
+        assert_eq!(
+            get_http_status_for_all(&[ERROR_USER_ALREADY_EXISTS, ERROR_PASSWORD_INCORRECT]),
+            400
+        );
+        assert_eq!(
+            get_http_status_for_all(&[ERROR_CREDENTIAL_DOES_NOT_EXIST, ERROR_INVALID_EMAIL]),
+            400
+        );
+    }
+
+    #[test]
+    fn no_errors_are_a_server_error() {
+        assert_eq!(get_http_status_for_all(&[]), 500);
+    }
+}
@@ -1 +1,6 @@
 pub const AUTH_TOKEN_EXPIRATION_TIME_MILLIS: i64 = 604800000; // 7 Days
+pub const REFRESH_TOKEN_EXPIRATION_TIME_MILLIS: i64 = 2592000000; // 30 Days
+pub const MAX_SESSION_AGE_MILLIS: i64 = 7776000000; // 90 Days
+pub const VERIFICATION_CODE_EXPIRATION_TIME_MILLIS: i64 = 900000; // 15 Minutes
+
+pub const MIN_TOKEN_JANITOR_INTERVAL_MILLIS: u64 = 1000; // 1 Second
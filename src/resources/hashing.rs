@@ -0,0 +1,19 @@
+//  Default cost parameters of each password hasher, following the OWASP password storage cheat sheet.
+
+pub const ARGON2_MEMORY_COST_KIB: u32 = 19456;
+pub const ARGON2_TIME_COST: u32 = 2;
+pub const ARGON2_PARALLELISM: u32 = 1;
+
+pub const SCRYPT_LOG_N: u8 = 17;
+pub const SCRYPT_BLOCK_SIZE: u32 = 8;
+pub const SCRYPT_PARALLELISM: u32 = 1;
+
+pub const BCRYPT_COST: u32 = 12;
+
+pub const PBKDF2_ITERATIONS: u32 = 210000;
+
+/// Length in bytes of the random salt generated for every new hash.
+pub const SALT_LENGTH: usize = 16;
+
+/// Password hashes computed at the same time. Further logins and registrations wait for a free slot.
+pub const MAX_CONCURRENT_HASHES: usize = 4;
@@ -0,0 +1,324 @@
+use crate::config::auth_config::AuthConfig;
+use crate::domain::credential::CredentialType;
+use crate::resources::error_messages::ErrorResource;
+use log::error;
+use std::collections::HashMap;
+
+pub const DEFAULT_LOCALE: &str = "en";
+
+const EMBEDDED_LOCALES: [(&str, &str); 3] = [
+    ("en", include_str!("locales/en.json")),
+    ("es", include_str!("locales/es.json")),
+    ("pt", include_str!("locales/pt.json")),
+];
+
+/// Human-readable messages for every `ERROR.*` key, per locale.
+/// Messages can hold `{name}` placeholders that get replaced by the parameters given when resolving them.
+#[derive(Debug, Clone)]
+pub struct MessageCatalog {
+    default_locale: String,
+    locales: HashMap<String, HashMap<String, String>>,
+}
+
+impl Default for MessageCatalog {
+    /// Catalog with the embedded English, Spanish and Portuguese messages.
+    fn default() -> Self {
+        let mut catalog = MessageCatalog::empty(DEFAULT_LOCALE);
+        for (locale, json) in EMBEDDED_LOCALES {
+            //  A broken embedded file only loses its own locale instead of panicking.
+            if let Err(error) = catalog.add_locale_from_json(locale, json) {
+                error!("Invalid embedded locale {}: {}", locale, error);
+            }
+        }
+        catalog
+    }
+}
+
+impl MessageCatalog {
+    /// Catalog without any messages. Messages not found fall back to the `ErrorResource`'s own message.
+    pub fn empty(default_locale: &str) -> MessageCatalog {
+        MessageCatalog {
+            default_locale: normalize_locale(default_locale),
+            locales: HashMap::new(),
+        }
+    }
+
+    /// Adds (or extends) a locale from a flat JSON object of `"ERROR.KEY": "message"` pairs.
+    pub fn add_locale_from_json(
+        &mut self,
+        locale: &str,
+        json: &str,
+    ) -> Result<(), serde_json::Error> {
+        let messages: HashMap<String, String> = serde_json::from_str(json)?;
+        self.locales
+            .entry(normalize_locale(locale))
+            .or_default()
+            .extend(messages);
+        Ok(())
+    }
+
+    pub fn get_locales(&self) -> Vec<&str> {
+        self.locales.keys().map(String::as_str).collect()
+    }
+
+    /// Picks the best locale this catalog has for an `Accept-Language`-style list,
+    /// like `es-MX,es;q=0.9,en;q=0.8`. Tries each tag by quality, then its primary language,
+    /// and falls back to the default locale.
+    pub fn resolve_locale(&self, accept_language: &str) -> &str {
+        for tag in parse_accept_language(accept_language) {
+            if let Some((locale, _)) = self.locales.get_key_value(&tag) {
+                return locale;
+            }
+            if let Some((primary, _)) = tag.split_once('-') {
+                if let Some((locale, _)) = self.locales.get_key_value(primary) {
+                    return locale;
+                }
+            }
+        }
+        &self.default_locale
+    }
+
+    /// Message for `key` in the best locale for `accept_language`, with its placeholders replaced by `parameters`.
+    /// Falls back to the default locale, then to `None` if no locale has the key.
+    pub fn get_message(
+        &self,
+        key: &str,
+        accept_language: &str,
+        parameters: &[(&str, String)],
+    ) -> Option<String> {
+        let locale = self.resolve_locale(accept_language);
+        let message = self
+            .locales
+            .get(locale)
+            .and_then(|messages| messages.get(key))
+            .or_else(|| {
+                self.locales
+                    .get(&self.default_locale)
+                    .and_then(|messages| messages.get(key))
+            })?;
+        Some(interpolate(message, parameters))
+    }
+
+    /// Localized message for an error, with the length limits of `config` filled in.
+    /// Falls back to the error's own message if no locale has its key.
+    pub fn localize(
+        &self,
+        error_resource: &ErrorResource,
+        accept_language: &str,
+        config: &AuthConfig,
+    ) -> String {
+        self.get_message(
+            error_resource.0,
+            accept_language,
+            &get_message_parameters(error_resource.0, config),
+        )
+        .unwrap_or_else(|| error_resource.1.to_string())
+    }
+
+    /// Localizes every error, keeping its key.
+    pub fn localize_all<'a>(
+        &self,
+        error_resources: &[ErrorResource<'a>],
+        accept_language: &str,
+        config: &AuthConfig,
+    ) -> Vec<(&'a str, String)> {
+        error_resources
+            .iter()
+            .map(|error_resource| {
+                (
+                    error_resource.0,
+                    self.localize(error_resource, accept_language, config),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Parameters the messages of `key` use, taken from the configuration.
+fn get_message_parameters(key: &str, config: &AuthConfig) -> Vec<(&'static str, String)> {
+    let (min, max) = match key {
+        "ERROR.INVALID_EMAIL" => get_credential_limits(config, &CredentialType::Email),
+        "ERROR.INVALID_PHONE_NUMBER" => get_credential_limits(config, &CredentialType::PhoneNumber),
+        "ERROR.INVALID_USERNAME" => get_credential_limits(config, &CredentialType::Username),
+        "ERROR.INVALID_NAME" => (config.min_name_length, config.max_name_length),
+        "ERROR.INVALID_PASSWORD" => (config.min_password_length, config.max_password_length),
+        "ERROR.TOO_MANY_CREDENTIALS" => (0, config.max_credentials_per_user),
+        "ERROR.PASSWORD_TOO_MANY_REPEATED_CHARACTERS" => (
+            0,
+            config
+                .password_policy
+                .max_repeated_characters
+                .unwrap_or(usize::MAX),
+        ),
+        "ERROR.PASSWORD_RECENTLY_USED" => (0, config.password_policy.history_depth),
+        _ => return Vec::new(),
+    };
+    vec![("min", min.to_string()), ("max", max.to_string())]
+}
+
+fn get_credential_limits(config: &AuthConfig, credential_type: &CredentialType) -> (usize, usize) {
+    (
+        config.get_min_credential_length(credential_type),
+        config.get_max_credential_length(credential_type),
+    )
+}
+
+fn interpolate(message: &str, parameters: &[(&str, String)]) -> String {
+    let mut interpolated = message.to_string();
+    for (name, value) in parameters {
+        interpolated = interpolated.replace(&format!("{{{}}}", name), value);
+    }
+    interpolated
+}
+
+fn normalize_locale(locale: &str) -> String {
+    locale.trim().replace('_', "-").to_lowercase()
+}
+
+/// Language tags of an `Accept-Language` header, best first. Tags with `q=0` and `*` are left out.
+fn parse_accept_language(accept_language: &str) -> Vec<String> {
+    let mut tags: Vec<(String, f32)> = accept_language
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let tag = normalize_locale(parts.next()?);
+            let quality = parts
+                .find_map(|parameter| parameter.trim().strip_prefix("q="))
+                .and_then(|quality| quality.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if tag.is_empty() || tag == "*" || quality <= 0.0 {
+                None
+            } else {
+                Some((tag, quality))
+            }
+        })
+        .collect();
+    //  Stable sort, so tags with the same quality keep the order the client sent them in.
+    tags.sort_by(|a, b| b.1.total_cmp(&a.1));
+    tags.into_iter().map(|(tag, _)| tag).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_accept_language_orders_tags_by_quality() {
+        assert_eq!(
+            parse_accept_language("en;q=0.8, es-MX, pt;q=0.9"),
+            vec!["es-mx", "pt", "en"]
+        );
+    }
+
+    #[test]
+    fn parse_accept_language_keeps_client_order_for_equal_quality() {
+        assert_eq!(parse_accept_language("pt, en, es"), vec!["pt", "en", "es"]);
+    }
+
+    #[test]
+    fn parse_accept_language_drops_wildcards_zero_quality_and_empty_tags() {
+        assert_eq!(
+            parse_accept_language("*, fr;q=0, , es_ES;q=0.5"),
+            vec!["es-es"]
+        );
+        assert!(parse_accept_language("").is_empty());
+    }
+
+    #[test]
+    fn parse_accept_language_treats_invalid_quality_as_one() {
+        assert_eq!(
+            parse_accept_language("en;q=0.5, es;q=abc"),
+            vec!["es", "en"]
+        );
+    }
+
+    #[test]
+    fn resolve_locale_falls_back_to_primary_language() {
+        let catalog = MessageCatalog::default();
+        assert_eq!(catalog.resolve_locale("es-MX"), "es");
+        assert_eq!(catalog.resolve_locale("pt_BR;q=0.9, fr"), "pt");
+    }
+
+    #[test]
+    fn resolve_locale_prefers_exact_tag() {
+        let mut catalog = MessageCatalog::default();
+        catalog
+            .add_locale_from_json("es-MX", r#"{"ERROR.JOIN": "¡Órale!"}"#)
+            .unwrap();
+        assert_eq!(catalog.resolve_locale("es-MX"), "es-mx");
+        assert_eq!(catalog.resolve_locale("es-AR"), "es");
+    }
+
+    #[test]
+    fn resolve_locale_uses_default_for_wildcard_and_unknown_locales() {
+        let catalog = MessageCatalog::default();
+        assert_eq!(catalog.resolve_locale("*"), DEFAULT_LOCALE);
+        assert_eq!(catalog.resolve_locale("fr, de;q=0.5"), DEFAULT_LOCALE);
+        assert_eq!(catalog.resolve_locale(""), DEFAULT_LOCALE);
+    }
+
+    #[test]
+    fn get_message_falls_back_to_default_locale_for_missing_keys() {
+        let mut catalog = MessageCatalog::empty("en");
+        catalog
+            .add_locale_from_json("en", r#"{"ERROR.A": "a", "ERROR.B": "b"}"#)
+            .unwrap();
+        catalog
+            .add_locale_from_json("es", r#"{"ERROR.A": "á"}"#)
+            .unwrap();
+        assert_eq!(
+            catalog.get_message("ERROR.A", "es", &[]),
+            Some("á".to_string())
+        );
+        assert_eq!(
+            catalog.get_message("ERROR.B", "es", &[]),
+            Some("b".to_string())
+        );
+        assert_eq!(catalog.get_message("ERROR.C", "es", &[]), None);
+    }
+
+    #[test]
+    fn interpolate_replaces_every_placeholder() {
+        let parameters = [("min", "4".to_string()), ("max", "64".to_string())];
+        assert_eq!(
+            interpolate("Between {min} and {max}, not {max}.", &parameters),
+            "Between 4 and 64, not 64."
+        );
+    }
+
+    #[test]
+    fn interpolate_leaves_unknown_placeholders_alone() {
+        assert_eq!(
+            interpolate("{min} and {other}", &[("min", "1".to_string())]),
+            "1 and {other}"
+        );
+        assert_eq!(interpolate("No placeholders", &[]), "No placeholders");
+    }
+
+    #[test]
+    fn recently_used_password_counts_the_history_next_to_the_current_password() {
+        let mut config = AuthConfig::default();
+        config.password_policy.history_depth = 3;
+        let message = MessageCatalog::default().localize(
+            &crate::resources::error_messages::ERROR_PASSWORD_RECENTLY_USED,
+            "en",
+            &config,
+        );
+        assert_eq!(
+            message,
+            "You have used this password recently. Choose one that's different from your current password and your last 3."
+        );
+    }
+
+    #[test]
+    fn embedded_locales_share_the_same_keys() {
+        let catalog = MessageCatalog::default();
+        let mut english: Vec<&String> = catalog.locales["en"].keys().collect();
+        english.sort();
+        for locale in ["es", "pt"] {
+            let mut keys: Vec<&String> = catalog.locales[locale].keys().collect();
+            keys.sort();
+            assert_eq!(keys, english, "{} is out of sync with en", locale);
+        }
+    }
+}
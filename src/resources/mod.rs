@@ -1,3 +1,6 @@
 pub mod error_messages;
 pub mod expirations;
+pub mod hashing;
+pub mod message_catalog;
+pub mod password_policy;
 pub mod variable_lengths;
@@ -0,0 +1,25 @@
+//  Defaults of the password policy and the data the strength estimator uses.
+
+/// Longest run of the same character allowed in a password.
+pub const MAX_REPEATED_PASSWORD_CHARACTERS: usize = 3;
+
+/// Minimum strength score (0 to 4) a password needs.
+pub const MIN_PASSWORD_STRENGTH_SCORE: u8 = 2;
+
+/// Previous passwords kept per user that can't be set again.
+pub const PASSWORD_HISTORY_DEPTH: usize = 5;
+
+/// Shortest name or credential fragment that passwords get checked against.
+pub const MIN_PERSONAL_INFORMATION_LENGTH: usize = 3;
+
+/// Words found in most leaked password lists. Passwords built around them are cheap to guess.
+pub const COMMON_PASSWORD_WORDS: [&str; 40] = [
+    "password", "passw0rd", "qwerty", "azerty", "letmein", "welcome", "admin", "login", "iloveyou",
+    "monkey", "dragon", "football", "baseball", "soccer", "master", "shadow", "sunshine",
+    "princess", "superman", "batman", "trustno1", "whatever", "freedom", "starwars", "computer",
+    "secret", "hello", "charlie", "michael", "jordan", "access", "mustang", "pokemon", "flower",
+    "summer", "winter", "spring", "autumn", "changeme", "default",
+];
+
+/// Keyboard rows, so walks like `qwerty` or `asdf` count as predictable sequences.
+pub const KEYBOARD_ROWS: [&str; 4] = ["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
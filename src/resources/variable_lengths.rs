@@ -2,7 +2,7 @@ pub const MIN_EMAIL_LENGTH: usize = 4;
 pub const MAX_EMAIL_LENGTH: usize = 254;
 
 pub const MIN_PHONE_NUMBER_LENGTH: usize = 8;
-pub const MAX_PHONE_NUMBER_LENGTH: usize = 14;
+pub const MAX_PHONE_NUMBER_LENGTH: usize = 16; // E.164: `+` and up to 15 digits
 
 pub const MIN_USERNAME_LENGTH: usize = 3;
 pub const MAX_USERNAME_LENGTH: usize = 64;
@@ -12,3 +12,8 @@ pub const MAX_NAME_LENGTH: usize = 254;
 
 pub const MIN_PASSWORD_LENGTH: usize = 8;
 pub const MAX_PASSWORD_LENGTH: usize = 128;
+
+pub const MAX_CREDENTIALS_PER_USER: usize = 3;
+
+pub const VERIFICATION_CODE_LENGTH: usize = 6;
+pub const MAX_VERIFICATION_ATTEMPTS: i32 = 5;
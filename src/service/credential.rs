@@ -0,0 +1,269 @@
+use crate::config::auth_config::AuthConfig;
+use crate::dao::credential::{
+    delete_credential, fetch_all_credentials, fetch_user_credentials, get_credential,
+    insert_credential, replace_credential_value, update_credential as update_persisted_credential,
+};
+use crate::domain::credential::{Credential, CredentialCollision, CredentialType};
+use crate::domain::error::UserLibError;
+use crate::dto::credential::CredentialDto;
+use crate::dto::token::AuthenticateUserDto;
+use crate::resources::error_messages::{
+    ErrorResource, ERROR_CANNOT_REMOVE_LAST_CREDENTIAL, ERROR_CREDENTIAL_DOES_NOT_EXIST,
+    ERROR_CREDENTIAL_TYPE_ALREADY_EXISTS, ERROR_TOO_MANY_CREDENTIALS, ERROR_USER_ALREADY_EXISTS,
+};
+use crate::service::user::authenticate_user;
+use crate::utils::canonicalization::{canonicalize_credential, canonicalize_credential_dto};
+use crate::validation::user_validator::validate_credential;
+use log::{error, warn};
+use sqlx::PgConnection;
+use std::collections::BTreeMap;
+
+/// Adds a credential to the authenticated user. Users can only have one credential of each type.
+/// The new credential starts unverified.
+pub async fn add_credential(
+    conn: &mut PgConnection,
+    config: &AuthConfig,
+    user: AuthenticateUserDto,
+    credential: CredentialDto,
+) -> Result<Credential, UserLibError> {
+    let persisted_user = authenticate_user(conn, config, user).await?;
+    let credential = canonicalize_credential_dto(config, credential);
+    validate_credential_dto(config, &credential)?;
+    let persisted_credentials = get_persisted_credentials(conn, &persisted_user.id).await?;
+    if persisted_credentials
+        .iter()
+        .any(|persisted| persisted.credential_type == credential.credential_type)
+    {
+        return Err(UserLibError::Conflict(ERROR_CREDENTIAL_TYPE_ALREADY_EXISTS));
+    }
+    if persisted_credentials.len() >= config.max_credentials_per_user {
+        return Err(UserLibError::Validation(ERROR_TOO_MANY_CREDENTIALS));
+    }
+    ensure_credential_is_free(conn, &credential).await?;
+
+    match insert_credential(conn, credential, &persisted_user.id).await {
+        Ok(persisted_credential) => Ok(persisted_credential),
+        Err(e) => {
+            error!("{}", e);
+            Err(UserLibError::Storage(e))
+        }
+    }
+}
+
+/// Changes the value of the authenticated user's credential of `credential.credential_type`.
+/// A changed value has to be verified again.
+pub async fn update_credential(
+    conn: &mut PgConnection,
+    config: &AuthConfig,
+    user: AuthenticateUserDto,
+    credential: CredentialDto,
+) -> Result<Credential, UserLibError> {
+    let persisted_user = authenticate_user(conn, config, user).await?;
+    let credential = canonicalize_credential_dto(config, credential);
+    validate_credential_dto(config, &credential)?;
+    let persisted_credential = match get_persisted_credentials(conn, &persisted_user.id)
+        .await?
+        .into_iter()
+        .find(|persisted| persisted.credential_type == credential.credential_type)
+    {
+        Some(persisted_credential) => persisted_credential,
+        None => {
+            return Err(UserLibError::Authentication(
+                ERROR_CREDENTIAL_DOES_NOT_EXIST,
+            ))
+        }
+    };
+    //  Nothing changes, so it stays verified
+    if persisted_credential.credential == credential.credential {
+        return Ok(persisted_credential);
+    }
+    ensure_credential_is_free(conn, &credential).await?;
+
+    match update_persisted_credential(
+        conn,
+        &persisted_user.id,
+        &credential.credential_type,
+        &credential.credential,
+    )
+    .await
+    {
+        Ok(Some(updated_credential)) => Ok(updated_credential),
+        Ok(None) => Err(UserLibError::Authentication(
+            ERROR_CREDENTIAL_DOES_NOT_EXIST,
+        )),
+        Err(e) => {
+            error!("{}", e);
+            Err(UserLibError::Storage(e))
+        }
+    }
+}
+
+/// Removes the authenticated user's credential of `credential_type`.
+/// The last credential can't be removed, the user wouldn't be able to log in anymore.
+pub async fn remove_credential(
+    conn: &mut PgConnection,
+    config: &AuthConfig,
+    user: AuthenticateUserDto,
+    credential_type: CredentialType,
+) -> Result<Credential, UserLibError> {
+    let persisted_user = authenticate_user(conn, config, user).await?;
+    let persisted_credentials = get_persisted_credentials(conn, &persisted_user.id).await?;
+    if !persisted_credentials
+        .iter()
+        .any(|persisted| persisted.credential_type == credential_type)
+    {
+        return Err(UserLibError::Authentication(
+            ERROR_CREDENTIAL_DOES_NOT_EXIST,
+        ));
+    }
+    if persisted_credentials.len() <= 1 {
+        return Err(UserLibError::Conflict(ERROR_CANNOT_REMOVE_LAST_CREDENTIAL));
+    }
+
+    match delete_credential(conn, &persisted_user.id, &credential_type).await {
+        Ok(Some(removed_credential)) => Ok(removed_credential),
+        Ok(None) => Err(UserLibError::Authentication(
+            ERROR_CREDENTIAL_DOES_NOT_EXIST,
+        )),
+        Err(e) => {
+            error!("{}", e);
+            Err(UserLibError::Storage(e))
+        }
+    }
+}
+
+/// Migration helper for credentials stored before they were canonicalized.
+/// Returns every group of stored credentials that share a canonical form, changing nothing.
+pub async fn find_credential_collisions(
+    conn: &mut PgConnection,
+    config: &AuthConfig,
+) -> Result<Vec<CredentialCollision>, UserLibError> {
+    let persisted_credentials = match fetch_all_credentials(conn).await {
+        Ok(persisted_credentials) => persisted_credentials,
+        Err(e) => {
+            error!("{}", e);
+            return Err(UserLibError::Storage(e));
+        }
+    };
+    Ok(group_by_canonical_credential(config, persisted_credentials)
+        .into_iter()
+        .filter(|(_, credentials)| credentials.len() > 1)
+        .map(
+            |((credential_type, canonical_credential), credentials)| CredentialCollision {
+                credential_type,
+                canonical_credential,
+                credentials,
+            },
+        )
+        .collect())
+}
+
+/// Migration helper that rewrites stored credentials into their canonical form, keeping `validated`.
+/// Collisions are left as they are and returned, resolve them and run it again.
+pub async fn canonicalize_persisted_credentials(
+    conn: &mut PgConnection,
+    config: &AuthConfig,
+) -> Result<Vec<CredentialCollision>, UserLibError> {
+    let persisted_credentials = match fetch_all_credentials(conn).await {
+        Ok(persisted_credentials) => persisted_credentials,
+        Err(e) => {
+            error!("{}", e);
+            return Err(UserLibError::Storage(e));
+        }
+    };
+    let mut collisions = Vec::new();
+    for ((credential_type, canonical_credential), mut credentials) in
+        group_by_canonical_credential(config, persisted_credentials)
+    {
+        if credentials.len() > 1 {
+            warn!(
+                "{} credentials canonicalize to {}, leaving them as they are.",
+                credentials.len(),
+                canonical_credential
+            );
+            collisions.push(CredentialCollision {
+                credential_type,
+                canonical_credential,
+                credentials,
+            });
+            continue;
+        }
+        let persisted_credential = credentials.remove(0);
+        if persisted_credential.credential == canonical_credential {
+            continue;
+        }
+        if let Err(e) = replace_credential_value(
+            conn,
+            &persisted_credential.user_id,
+            &persisted_credential.credential_type,
+            &canonical_credential,
+        )
+        .await
+        {
+            error!("{}", e);
+            return Err(UserLibError::Storage(e));
+        }
+    }
+    Ok(collisions)
+}
+
+fn group_by_canonical_credential(
+    config: &AuthConfig,
+    credentials: Vec<Credential>,
+) -> BTreeMap<(CredentialType, String), Vec<Credential>> {
+    let mut groups: BTreeMap<(CredentialType, String), Vec<Credential>> = BTreeMap::new();
+    for credential in credentials {
+        let canonical_credential =
+            canonicalize_credential(config, &credential.credential, &credential.credential_type);
+        groups
+            .entry((credential.credential_type.clone(), canonical_credential))
+            .or_default()
+            .push(credential);
+    }
+    groups
+}
+
+fn validate_credential_dto(
+    config: &AuthConfig,
+    credential: &CredentialDto,
+) -> Result<(), UserLibError> {
+    let mut error_resources: Vec<ErrorResource> = Vec::new();
+    validate_credential(
+        &mut error_resources,
+        config,
+        &credential.credential,
+        &credential.credential_type,
+    );
+    match error_resources.pop() {
+        None => Ok(()),
+        Some(error_resource) => Err(UserLibError::Validation(error_resource)),
+    }
+}
+
+async fn get_persisted_credentials(
+    conn: &mut PgConnection,
+    user_id: &i32,
+) -> Result<Vec<Credential>, UserLibError> {
+    match fetch_user_credentials(conn, user_id).await {
+        Ok(persisted_credentials) => Ok(persisted_credentials),
+        Err(e) => {
+            error!("{}", e);
+            Err(UserLibError::Storage(e))
+        }
+    }
+}
+
+/// Fails if any user, including this one, already has `credential` as a credential of the same type.
+async fn ensure_credential_is_free(
+    conn: &mut PgConnection,
+    credential: &CredentialDto,
+) -> Result<(), UserLibError> {
+    match get_credential(conn, &credential.credential_type, &credential.credential).await {
+        Ok(None) => Ok(()),
+        Ok(Some(_)) => Err(UserLibError::Conflict(ERROR_USER_ALREADY_EXISTS)),
+        Err(e) => {
+            error!("{}", e);
+            Err(UserLibError::Storage(e))
+        }
+    }
+}
@@ -1,2 +1,5 @@
+pub mod credential;
 pub mod token;
 pub mod user;
+pub mod user_service;
+pub mod verification;
@@ -1 +1,129 @@
+use crate::config::auth_config::AuthConfig;
+use crate::dao::token::{
+    delete_expired_tokens, get_user_sessions, remove_all_user_tokens, remove_token,
+    validate_user_token,
+};
+use crate::domain::error::UserLibError;
+use crate::domain::token::{Session, Token};
+use crate::dto::token::AuthenticateUserDto;
+use crate::resources::error_messages::ERROR_INCORRECT_TOKEN;
+use crate::resources::expirations::MIN_TOKEN_JANITOR_INTERVAL_MILLIS;
+use chrono::{DateTime, Duration, Utc};
+use log::{error, info};
+use sqlx::{PgConnection, PgPool};
+use tokio::task::JoinHandle;
 
+/// Ends the session that owns the supplied auth token.
+/// The token is only removed if it belongs to the user in the payload.
+pub async fn logout(
+    conn: &mut PgConnection,
+    user: AuthenticateUserDto,
+) -> Result<Token, UserLibError> {
+    let persisted_token = match validate_user_token(conn, &user.id, user.auth_token).await {
+        Ok(persisted_token_opt) => match persisted_token_opt {
+            None => return Err(UserLibError::Token(ERROR_INCORRECT_TOKEN)),
+            Some(persisted_token) => persisted_token,
+        },
+        Err(error) => {
+            error!("{:?}", error);
+            return Err(UserLibError::Storage(error));
+        }
+    };
+    revoke_session(conn, &persisted_token.user_id, &persisted_token.id).await
+}
+
+/// Ends every session a user has open, returning the tokens that were removed.
+pub async fn logout_all_sessions(
+    conn: &mut PgConnection,
+    user_id: &i32,
+) -> Result<Vec<Token>, UserLibError> {
+    match remove_all_user_tokens(conn, user_id).await {
+        Ok(removed_tokens) => Ok(removed_tokens),
+        Err(error) => {
+            error!("{:?}", error);
+            Err(UserLibError::Storage(error))
+        }
+    }
+}
+
+/// Lists the open sessions of a user, most recently used first, so one of them can be revoked.
+pub async fn list_sessions(
+    conn: &mut PgConnection,
+    user_id: &i32,
+) -> Result<Vec<Session>, UserLibError> {
+    match get_user_sessions(conn, user_id).await {
+        Ok(sessions) => Ok(sessions),
+        Err(error) => {
+            error!("{:?}", error);
+            Err(UserLibError::Storage(error))
+        }
+    }
+}
+
+/// Ends a single session of a user by its token id.
+/// Fails with `ERROR_INCORRECT_TOKEN` if the token doesn't exist or belongs to someone else.
+pub async fn revoke_session(
+    conn: &mut PgConnection,
+    user_id: &i32,
+    token_id: &i32,
+) -> Result<Token, UserLibError> {
+    match remove_token(conn, user_id, token_id).await {
+        Ok(removed_token_opt) => match removed_token_opt {
+            None => Err(UserLibError::Token(ERROR_INCORRECT_TOKEN)),
+            Some(removed_token) => Ok(removed_token),
+        },
+        Err(error) => {
+            error!("{:?}", error);
+            Err(UserLibError::Storage(error))
+        }
+    }
+}
+
+/// Deletes every session that wasn't used or refreshed since `older_than`,
+/// and every session older than `config.max_session_age_millis`. Returns the amount of deleted sessions.
+pub async fn purge_expired_tokens(
+    conn: &mut PgConnection,
+    config: &AuthConfig,
+    older_than: DateTime<Utc>,
+) -> Result<u64, UserLibError> {
+    let created_before = Utc::now() - Duration::milliseconds(config.max_session_age_millis);
+    match delete_expired_tokens(conn, older_than, created_before).await {
+        Ok(deleted_rows) => Ok(deleted_rows),
+        Err(error) => {
+            error!("{:?}", error);
+            Err(UserLibError::Storage(error))
+        }
+    }
+}
+
+/// Spawns a task that purges every session whose refresh token expired, once every `interval`.
+/// The first purge runs right away. Abort the returned handle to stop it.
+/// Intervals shorter than `MIN_TOKEN_JANITOR_INTERVAL_MILLIS`, like `Duration::ZERO`, are raised to it.
+pub fn spawn_expired_token_janitor(
+    pool: PgPool,
+    config: AuthConfig,
+    interval: std::time::Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let min_interval = std::time::Duration::from_millis(MIN_TOKEN_JANITOR_INTERVAL_MILLIS);
+        let mut ticker = tokio::time::interval(interval.max(min_interval));
+        loop {
+            ticker.tick().await;
+            let mut conn = match pool.acquire().await {
+                Ok(conn) => conn,
+                Err(error) => {
+                    error!(
+                        "Expired token janitor couldn't get a connection: {:?}",
+                        error
+                    );
+                    continue;
+                }
+            };
+            let older_than =
+                Utc::now() - Duration::milliseconds(config.refresh_token_expiration_time_millis);
+            if let Ok(deleted_rows) = purge_expired_tokens(&mut conn, &config, older_than).await {
+                info!("Expired token janitor deleted {} sessions.", deleted_rows);
+            }
+        }
+    })
+}
@@ -1,76 +1,109 @@
+use crate::config::auth_config::AuthConfig;
 use crate::dao::credential::{fetch_user_credentials, get_credential, insert_credential};
-use crate::dao::token::{insert_token, update_token, validate_user_token};
+use crate::dao::password_history::{
+    fetch_password_history, insert_password_history, trim_password_history,
+};
+use crate::dao::token::{
+    get_retired_refresh_token, get_token_with_refresh_token, insert_token, remove_token,
+    update_token, validate_user_token,
+};
 use crate::dao::user::{get_user_with_id, insert_user, update_user};
 use crate::domain::credential::Credential;
+use crate::domain::error::{PasswordHashError, TokenGenerationError, UserLibError};
 use crate::domain::token::Token;
 use crate::domain::user::User;
 use crate::dto::token::{AuthenticateUserDto, RefreshAuthTokenForUserDto};
 use crate::dto::users::{UserLoginPayload, UserRegisterPayload, UserResetPasswordPayload};
 use crate::resources::error_messages::{
-    ErrorResource, ERROR_CREDENTIAL_DOES_NOT_EXIST, ERROR_EXPIRED_TOKEN, ERROR_INCORRECT_TOKEN,
-    ERROR_PASSWORD_INCORRECT, ERROR_TOKEN_NOT_CREATED, ERROR_TOO_MANY_CREDENTIALS,
-    ERROR_USER_ALREADY_EXISTS, ERROR_USER_DOES_NOT_EXIST,
+    ErrorResource, ERROR_CREDENTIAL_DOES_NOT_EXIST, ERROR_CRITICAL_USER_MISSING,
+    ERROR_EXPIRED_REFRESH_TOKEN, ERROR_EXPIRED_SESSION, ERROR_EXPIRED_TOKEN,
+    ERROR_HASHING_PASSWORD, ERROR_INCORRECT_TOKEN, ERROR_JOIN, ERROR_PASSWORD_INCORRECT,
+    ERROR_PASSWORD_RECENTLY_USED, ERROR_REUSED_REFRESH_TOKEN, ERROR_TOKEN_NOT_CREATED,
+    ERROR_TOO_MANY_CREDENTIALS, ERROR_USER_ALREADY_EXISTS, ERROR_USER_DOES_NOT_EXIST,
 };
-use crate::resources::expirations::AUTH_TOKEN_EXPIRATION_TIME_MILLIS;
+use crate::utils::canonicalization::{canonicalize_credential, canonicalize_credential_dto};
 use crate::utils::hasher::{
-    generate_multiple_random_token_with_rng, hash_password, hash_password_with_existing_salt,
+    generate_multiple_random_token_with_rng, hash_password, password_needs_rehash, verify_password,
+};
+use crate::validation::user_validator::{
+    validate_new_password, validate_password_not_breached, validate_user_for_creation,
+    validate_user_for_password_authentication,
 };
-use crate::validation::user_validator::validate_user_for_creation;
-use chrono::Utc;
-use log::{debug, error};
-use sqlx::{PgConnection, Postgres, Transaction};
+use chrono::{Duration, Utc};
+use log::{debug, error, warn};
+use sqlx::PgConnection;
 
-pub async fn register_user<'a>(
+pub async fn register_user(
     transaction: &mut PgConnection,
+    config: &AuthConfig,
     user: UserRegisterPayload,
-) -> Result<Token, Vec<ErrorResource<'a>>> {
+) -> Result<Token, UserLibError> {
+    //  Everything below, from validation to storage, works on the canonical credentials
+    let user = UserRegisterPayload {
+        credentials: user
+            .credentials
+            .into_iter()
+            .map(|credential_dto| canonicalize_credential_dto(config, credential_dto))
+            .collect(),
+        ..user
+    };
     let mut error_resources: Vec<ErrorResource> = Vec::new();
     //  Validate user
-    validate_user_for_creation(&user, &mut error_resources);
-    //  Find if user exists
-    if user.credentials.len() > 3 {
+    validate_user_for_creation(&user, config, &mut error_resources);
+    validate_password_not_breached(&mut error_resources, config, &user.password).await?;
+    if user.credentials.len() > config.max_credentials_per_user {
         error_resources.push(ERROR_TOO_MANY_CREDENTIALS);
     }
+    let mut errors: Vec<UserLibError> = error_resources
+        .into_iter()
+        .map(UserLibError::Validation)
+        .collect();
+    //  Find if user exists
     for credential_dto in user.credentials.iter() {
-        match get_credential(transaction, credential_dto.credential.clone()).await {
+        match get_credential(
+            transaction,
+            &credential_dto.credential_type,
+            &credential_dto.credential,
+        )
+        .await
+        {
             Ok(credential_opt) => match credential_opt {
                 None => {}
                 Some(_) => {
-                    error_resources.push(ERROR_USER_ALREADY_EXISTS);
+                    errors.push(UserLibError::Conflict(ERROR_USER_ALREADY_EXISTS));
                 }
             },
             Err(e) => {
                 error!("{}", e);
-                error_resources.push(("ERROR.DATABASE_ERROR", ""));
+                errors.push(UserLibError::Storage(e));
             }
         };
     }
     //  If validation gave any errors blow up and send them back to the client
-    if error_resources.len() > 0 {
-        return Err(error_resources);
+    if !errors.is_empty() {
+        return Err(UserLibError::from_errors(errors));
     }
-    //  Get salt and hashed password from hashing function then give the results to the user
-    let hash_result = hash_password(&user.password);
+    //  Hash the password with the configured algorithm, salt and parameters are part of the result
+    let (password_hash, pepper_key_id) = match hash_password(config, &user.password).await {
+        Ok(password_hash) => password_hash,
+        Err(e) => return Err(hashing_error(e)),
+    };
     let now = Utc::now();
     let user_to_insert = User {
         id: 0,
         name: user.name,
-        password: hash_result.hash,
-        salt: hash_result.salt,
+        password: password_hash,
+        pepper_key_id,
         time_created: now,
         last_updated: now,
     };
 
-    let persisted_user;
     //  Insert user in DB
-    match insert_user(transaction, user_to_insert).await {
-        Ok(user) => {
-            persisted_user = user;
-        }
+    let persisted_user = match insert_user(transaction, user_to_insert).await {
+        Ok(user) => user,
         Err(e) => {
             error!("{}", e);
-            error_resources.push(("ERROR.DATABASE_ERROR", ""));
-            return Err(error_resources);
+            return Err(UserLibError::Storage(e));
         }
     };
 
@@ -80,55 +113,42 @@ pub async fn register_user<'a>(
             Ok(_) => {}
             Err(e) => {
                 error!("{}", e);
-                error_resources.push(("ERROR.DATABASE_ERROR", ""));
-                return Err(error_resources);
+                return Err(UserLibError::Storage(e));
             }
         };
     }
 
-    if let Some(persisted_token) =
-        create_token_for_user(transaction, persisted_user.id, &mut error_resources).await
-    {
-        Ok(persisted_token)
-    } else {
-        Err(error_resources)
-    }
+    create_token_for_user(transaction, persisted_user.id).await
 }
-pub async fn authenticate_user<'a>(
+pub async fn authenticate_user(
     conn: &mut PgConnection,
+    config: &AuthConfig,
     user: AuthenticateUserDto,
-) -> Result<User, Vec<ErrorResource<'a>>> {
-    let mut error_resources = Vec::new();
+) -> Result<User, UserLibError> {
     let persisted_user = match get_user_with_id(conn, &user.id).await {
         Ok(persisted_user_opt) => match persisted_user_opt {
             None => {
-                error_resources.push(ERROR_USER_DOES_NOT_EXIST);
-                return Err(error_resources);
+                return Err(UserLibError::Authentication(ERROR_USER_DOES_NOT_EXIST));
             }
             Some(persisted_user) => persisted_user,
         },
         Err(error) => {
             error!("{:?}", error);
-            error_resources.push(("ERROR.DATABASE_ERROR", ""));
-            return Err(error_resources);
+            return Err(UserLibError::Storage(error));
         }
     };
 
     match validate_user_token(conn, &user.id, user.auth_token).await {
         Ok(persisted_token_opt) => match persisted_token_opt {
-            None => {
-                error_resources.push(ERROR_INCORRECT_TOKEN);
-                Err(error_resources)
-            }
+            None => Err(UserLibError::Token(ERROR_INCORRECT_TOKEN)),
             Some(persisted_token) => {
                 // Check if persisted_token expired
                 if Utc::now().timestamp_millis() - persisted_token.last_updated.timestamp_millis()
-                    > AUTH_TOKEN_EXPIRATION_TIME_MILLIS
+                    > config.auth_token_expiration_time_millis
                 {
                     // Expired
                     debug!("Expired token: {:?}", persisted_token);
-                    error_resources.push(ERROR_EXPIRED_TOKEN);
-                    Err(error_resources)
+                    Err(UserLibError::Token(ERROR_EXPIRED_TOKEN))
                 } else {
                     // Not expired
                     Ok(persisted_user)
@@ -137,274 +157,507 @@ pub async fn authenticate_user<'a>(
         },
         Err(error) => {
             error!("{:?}", error);
-            error_resources.push(("ERROR.DATABASE_ERROR", ""));
-            Err(error_resources)
+            Err(UserLibError::Storage(error))
         }
     }
 }
 
-///
-pub async fn refresh_auth_token<'a>(
+/// Issues a new auth token and a new refresh token for the user's session that owns the supplied refresh token.
+/// Fails with `ERROR_INCORRECT_TOKEN` if the refresh token doesn't belong to the user,
+/// with `ERROR_EXPIRED_REFRESH_TOKEN` if it's older than `config.refresh_token_expiration_time_millis`
+/// and with `ERROR_EXPIRED_SESSION` if the session is older than `config.max_session_age_millis`.
+/// The supplied refresh token is retired. If a retired refresh token is presented again the whole
+/// session is revoked and `ERROR_REUSED_REFRESH_TOKEN` is returned; that revocation is still written to
+/// `conn`, so commit the transaction even when this error comes back.
+pub async fn refresh_auth_token(
     conn: &mut PgConnection,
+    config: &AuthConfig,
     user: RefreshAuthTokenForUserDto,
-) -> Result<Token, Vec<ErrorResource>> {
-    let mut error_resources = Vec::new();
+) -> Result<Token, UserLibError> {
     let _persisted_user = match get_user_with_id(conn, &user.id).await {
         Ok(persisted_user_opt) => match persisted_user_opt {
             None => {
-                error_resources.push(ERROR_USER_DOES_NOT_EXIST);
-                return Err(error_resources);
+                return Err(UserLibError::Authentication(ERROR_USER_DOES_NOT_EXIST));
             }
             Some(persisted_user) => persisted_user,
         },
         Err(error) => {
             error!("{:?}", error);
-            error_resources.push(("ERROR.DATABASE_ERROR", ""));
-            return Err(error_resources);
+            return Err(UserLibError::Storage(error));
         }
     };
 
-    let mut tokens: Vec<String> = match generate_multiple_random_token_with_rng(2).await {
-        Ok(tokens) => tokens,
+    let (new_auth_token, new_refresh_token) = generate_token_pair().await?;
+    let plaintext_tokens = (new_auth_token.clone(), new_refresh_token.clone());
+
+    let now = Utc::now();
+    //  Find the user's session that holds this refresh token, then rotate it if it hasn't expired.
+    match get_token_with_refresh_token(conn, &user.id, user.refresh_token.clone()).await {
+        Ok(None) => {}
+        Ok(Some(persisted_token)) => {
+            if now.timestamp_millis() - persisted_token.time_created.timestamp_millis()
+                > config.max_session_age_millis
+            {
+                debug!("Expired session: {:?}", persisted_token);
+                return Err(UserLibError::Token(ERROR_EXPIRED_SESSION));
+            }
+            if now.timestamp_millis() - persisted_token.last_updated.timestamp_millis()
+                > config.refresh_token_expiration_time_millis
+            {
+                debug!("Expired refresh token: {:?}", persisted_token);
+                return Err(UserLibError::Token(ERROR_EXPIRED_REFRESH_TOKEN));
+            }
+            match update_token(
+                conn,
+                &persisted_token,
+                new_auth_token,
+                new_refresh_token,
+                now - Duration::milliseconds(config.refresh_token_expiration_time_millis),
+                now - Duration::milliseconds(config.max_session_age_millis),
+            )
+            .await
+            {
+                Ok(Some(mut rotated_token)) => {
+                    //  Only the digests were persisted, hand the plaintext tokens back to the client.
+                    (rotated_token.auth_token, rotated_token.refresh_token) = plaintext_tokens;
+                    return Ok(rotated_token);
+                }
+                //  Another request rotated this session in the meantime, so the token is now retired.
+                Ok(None) => {}
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(UserLibError::Storage(e));
+                }
+            };
+        }
         Err(e) => {
-            error!("{}", e);
-            error_resources.push(("ERROR.JOIN_ERROR", ""));
-            return Err(error_resources);
+            error!("{:?}", e);
+            return Err(UserLibError::Storage(e));
         }
     };
 
-    if tokens.len() > 0 {
-        let new_auth_token = tokens.remove(0);
-        return match update_token(conn, user.refresh_token, new_auth_token).await {
-            Ok(persisted_token) => Ok(persisted_token),
-            Err(e) => {
+    //  None of the user's sessions holds this refresh token. If it was already rotated out, it's being replayed.
+    match get_retired_refresh_token(conn, &user.id, user.refresh_token).await {
+        Ok(None) => Err(UserLibError::Token(ERROR_INCORRECT_TOKEN)),
+        Ok(Some(retired_token)) => {
+            warn!(
+                "Retired refresh token reused. Revoking session {} of user {}.",
+                retired_token.token_id, retired_token.user_id
+            );
+            if let Err(e) =
+                remove_token(conn, &retired_token.user_id, &retired_token.token_id).await
+            {
                 error!("{:?}", e);
-                error_resources.push(("ERROR.DATABASE_ERROR", ""));
-                Err(error_resources)
+                return Err(UserLibError::Storage(e));
             }
-        };
+            Err(UserLibError::Token(ERROR_REUSED_REFRESH_TOKEN))
+        }
+        Err(e) => {
+            error!("{:?}", e);
+            Err(UserLibError::Storage(e))
+        }
     }
-
-    Err(error_resources)
 }
 
 /// reset a user's password by validating the user's own password.
 pub async fn reset_password(
     conn: &mut PgConnection,
+    config: &AuthConfig,
     user: UserResetPasswordPayload,
-) -> Result<User, Vec<ErrorResource>> {
-    let mut error_resources: Vec<ErrorResource> = Vec::new();
-
-    let password_matches = match validate_user_password(conn, &user.id, user.password).await {
-        Ok(matches) => matches,
-        Err(e) => {
-            error!("{:?}", e);
-            error_resources.push(e);
-            return Err(error_resources);
-        }
-    };
+) -> Result<User, UserLibError> {
+    let password_matches =
+        match validate_user_password(conn, config, &user.id, &user.password).await {
+            Ok(matches) => matches,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(e);
+            }
+        };
 
-    if let Some(persisted_user) = password_matches {
+    if let Some((persisted_user, _)) = password_matches {
         // Change pass
-        match change_password(conn, persisted_user, &user.new_password).await {
+        match change_password(conn, config, persisted_user, &user.new_password, true).await {
             Ok(user_changed) => Ok(user_changed),
             Err(e) => {
                 error!("{:?}", e);
-                error_resources.push(e);
-                Err(error_resources)
+                Err(e)
             }
         }
     } else {
-        error_resources.push(ERROR_PASSWORD_INCORRECT);
-        Err(error_resources)
+        Err(UserLibError::Authentication(ERROR_PASSWORD_INCORRECT))
     }
 }
 
-/// ## This resets a user's password without any validations!
-/// Don't expose this to any public endpoint!!
-pub async fn force_reset_password<'a>(
+/// ## This resets a user's password without validating the user's identity!
+/// Don't expose this to any public endpoint!! The new password is still checked against the length limits,
+/// the `PasswordPolicy` and the breached password list.
+pub async fn force_reset_password(
     conn: &mut PgConnection,
+    config: &AuthConfig,
     user_id: &i32,
     new_password: String,
-) -> Result<User, ErrorResource<'a>> {
+) -> Result<User, UserLibError> {
     let persisted_user = match get_user_with_id(conn, user_id).await {
         Ok(persisted_user_opt) => {
             match persisted_user_opt {
                 None => {
                     error!("Serious error. User doesn't exist but credentials pointing to the user do.");
-                    return Err((
-                        "ERROR.DATABASE_ERROR",
-                        "Critical. User doesn't exist but credentials pointing to the user do.",
-                    ));
+                    return Err(UserLibError::Internal {
+                        resource: ERROR_CRITICAL_USER_MISSING,
+                        source: None,
+                    });
                 }
                 Some(persisted_user) => persisted_user,
             }
         }
         Err(e) => {
             error!("{}", e);
-            return Err(("ERROR.DATABASE_ERROR", ""));
+            return Err(UserLibError::Storage(e));
         }
     };
-    Ok(change_password(conn, persisted_user, &new_password).await?)
+    let check_history = config.password_policy.check_history_on_force_reset;
+    change_password(conn, config, persisted_user, &new_password, check_history).await
 }
 
-///
-pub async fn password_login<'a>(
-    conn: &mut Transaction<'a, Postgres>,
+/// Logs a user in with one of their credentials and their password, creating a new token.
+/// Fails with `UserLibError::CredentialNotVerified` if the credential isn't verified and `config` requires it.
+pub async fn password_login(
+    conn: &mut PgConnection,
+    config: &AuthConfig,
     user: UserLoginPayload,
-) -> Result<Token, Vec<ErrorResource<'a>>> {
-    let mut error_resources = Vec::new();
-    let persisted_user_credential = match get_credential(conn, user.credential).await {
-        Ok(credential_opt) => match credential_opt {
-            None => {
-                error!("Credential not found for password login.");
-                error_resources.push(ERROR_CREDENTIAL_DOES_NOT_EXIST);
-                return Err(error_resources);
-            }
-            Some(persisted_credential) => persisted_credential,
-        },
-        Err(e) => {
-            error!("{}", e);
-            error_resources.push(("ERROR.DATABASE_ERROR", ""));
-            return Err(error_resources);
-        }
+) -> Result<Token, UserLibError> {
+    let user = UserLoginPayload {
+        credential: canonicalize_credential(config, &user.credential, &user.credential_type),
+        ..user
     };
-    let persisted_user_opt =
-        match validate_user_password(conn, &persisted_user_credential.user_id, user.password).await
-        {
-            Ok(matches) => matches,
+    let mut error_resources: Vec<ErrorResource> = Vec::new();
+    validate_user_for_password_authentication(&user, config, &mut error_resources);
+    if !error_resources.is_empty() {
+        return Err(UserLibError::from_errors(
+            error_resources
+                .into_iter()
+                .map(UserLibError::Validation)
+                .collect(),
+        ));
+    }
+    let persisted_user_credential =
+        match get_credential(conn, &user.credential_type, &user.credential).await {
+            Ok(credential_opt) => match credential_opt {
+                None => {
+                    error!("Credential not found for password login.");
+                    return Err(UserLibError::Authentication(
+                        ERROR_CREDENTIAL_DOES_NOT_EXIST,
+                    ));
+                }
+                Some(persisted_credential) => persisted_credential,
+            },
             Err(e) => {
-                error!("{:?}", e);
-                error_resources.push(e);
-                return Err(error_resources);
+                error!("{}", e);
+                return Err(UserLibError::Storage(e));
             }
         };
-    if let Some(_) = persisted_user_opt {
-        return if let Some(persisted_token) = create_token_for_user(
-            conn,
-            persisted_user_credential.user_id,
-            &mut error_resources,
-        )
-        .await
+    let persisted_user_opt = match validate_user_password(
+        conn,
+        config,
+        &persisted_user_credential.user_id,
+        &user.password,
+    )
+    .await
+    {
+        Ok(matches) => matches,
+        Err(e) => {
+            error!("{:?}", e);
+            return Err(e);
+        }
+    };
+    if let Some((persisted_user, needs_rehash)) = persisted_user_opt {
+        //  Checked after the password, so it doesn't tell whether a credential is verified to anyone else
+        if !persisted_user_credential.validated
+            && config.requires_verified_credential(&persisted_user_credential.credential_type)
         {
-            Ok(persisted_token)
-        } else {
-            Err(error_resources)
-        };
+            return Err(UserLibError::CredentialNotVerified {
+                user_id: persisted_user_credential.user_id,
+                credential_type: persisted_user_credential.credential_type,
+            });
+        }
+        if needs_rehash {
+            rehash_password(conn, config, persisted_user, &user.password).await?;
+        }
+        return create_token_for_user(conn, persisted_user_credential.user_id).await;
     }
 
-    Err(error_resources)
+    Err(UserLibError::Authentication(ERROR_PASSWORD_INCORRECT))
 }
 
-///
-pub async fn get_user_credentials<'a>(
-    transaction: &mut Transaction<'a, Postgres>,
+/// Returns every credential of an authenticated user.
+pub async fn get_user_credentials(
+    transaction: &mut PgConnection,
+    config: &AuthConfig,
     user: AuthenticateUserDto,
-) -> Result<Vec<Credential>, Vec<ErrorResource<'a>>> {
-    let mut error_resources = Vec::new();
-    let persisted_user = authenticate_user(transaction, user).await?;
+) -> Result<Vec<Credential>, UserLibError> {
+    let persisted_user = authenticate_user(transaction, config, user).await?;
     match fetch_user_credentials(transaction, &persisted_user.id).await {
         Ok(persisted_credentials) => Ok(persisted_credentials),
         Err(e) => {
             error!("{}", e);
-            error_resources.push(("ERROR.DATABASE_ERROR", ""));
-            Err(error_resources)
+            Err(UserLibError::Storage(e))
         }
     }
 }
 
-async fn create_token_for_user<'a>(
+async fn create_token_for_user(
     transaction: &mut PgConnection,
     user_id: i32,
-    error_resources: &mut Vec<ErrorResource<'a>>,
-) -> Option<Token> {
+) -> Result<Token, UserLibError> {
     //  Create token and send it back.
-    let tokens: Vec<String> = match generate_multiple_random_token_with_rng(2).await {
-        Ok(tokens) => tokens,
-        Err(e) => {
-            error!("{}", e);
-            error_resources.push(("ERROR.JOIN_ERROR", ""));
-            return None;
-        }
-    };
+    let (auth_token, refresh_token) = generate_token_pair().await?;
     let token_to_insert = Token {
         id: 0,
         user_id,
-        auth_token: match tokens.get(0) {
-            None => {
-                error!("Tokens were not created.",);
-                error_resources.push(ERROR_TOKEN_NOT_CREATED);
-                return None;
-            }
-            Some(token) => token.clone(),
-        },
-        refresh_token: match tokens.get(1) {
-            None => {
-                error!("Tokens were not created.",);
-                error_resources.push(ERROR_TOKEN_NOT_CREATED);
-                return None;
-            }
-            Some(token) => token.clone(),
-        },
+        auth_token,
+        refresh_token,
         time_created: Utc::now(),
         last_updated: Utc::now(),
     };
 
+    let plaintext_tokens = (
+        token_to_insert.auth_token.clone(),
+        token_to_insert.refresh_token.clone(),
+    );
+
     //  Insert token in DB
     match insert_token(transaction, token_to_insert).await {
-        Ok(persisted_token) => Some(persisted_token),
+        Ok(mut persisted_token) => {
+            //  Only the digests were persisted, hand the plaintext tokens back to the client.
+            (persisted_token.auth_token, persisted_token.refresh_token) = plaintext_tokens;
+            Ok(persisted_token)
+        }
         Err(e) => {
             error!("{}", e);
-            error_resources.push(("ERROR.DATABASE_ERROR", ""));
-            None
+            Err(UserLibError::Storage(e))
         }
     }
 }
 
-async fn validate_user_password<'a>(
+/// New auth and refresh tokens. Generation failures are returned instead of empty tokens.
+async fn generate_token_pair() -> Result<(String, String), UserLibError> {
+    let mut tokens = match generate_multiple_random_token_with_rng(2).await {
+        Ok(tokens) => tokens.into_iter(),
+        Err(TokenGenerationError::Join(e)) => {
+            error!("{}", e);
+            return Err(UserLibError::Internal {
+                resource: ERROR_JOIN,
+                source: Some(Box::new(e)),
+            });
+        }
+        Err(e) => {
+            error!("{}", e);
+            return Err(UserLibError::Internal {
+                resource: ERROR_TOKEN_NOT_CREATED,
+                source: Some(Box::new(e)),
+            });
+        }
+    };
+    match (tokens.next(), tokens.next()) {
+        (Some(auth_token), Some(refresh_token)) => Ok((auth_token, refresh_token)),
+        _ => {
+            error!("Tokens were not created.",);
+            Err(UserLibError::Internal {
+                resource: ERROR_TOKEN_NOT_CREATED,
+                source: Some(Box::new(TokenGenerationError::Empty)),
+            })
+        }
+    }
+}
+
+async fn validate_user_password(
     conn: &mut PgConnection,
+    config: &AuthConfig,
     user_id: &i32,
-    password: String,
-) -> Result<Option<User>, ErrorResource<'a>> {
+    password: &str,
+) -> Result<Option<(User, bool)>, UserLibError> {
     let persisted_user = match get_user_with_id(conn, user_id).await {
         Ok(persisted_user_opt) => {
             match persisted_user_opt {
                 None => {
                     error!("Serious error. User doesn't exist but credentials pointing to the user do.");
-                    return Err((
-                        "ERROR.DATABASE_ERROR",
-                        "Critical. User doesn't exist but credentials pointing to the user do.",
-                    ));
+                    return Err(UserLibError::Internal {
+                        resource: ERROR_CRITICAL_USER_MISSING,
+                        source: None,
+                    });
                 }
                 Some(persisted_user) => persisted_user,
             }
         }
         Err(e) => {
             error!("{}", e);
-            return Err(("ERROR.DATABASE_ERROR", ""));
+            return Err(UserLibError::Storage(e));
         }
     };
-    let hashed_password = hash_password_with_existing_salt(&password, &persisted_user.salt);
-    if hashed_password.hash == persisted_user.password {
-        Ok(Some(persisted_user))
-    } else {
-        Ok(None)
+    let pepper_key_id = persisted_user.pepper_key_id.as_deref();
+    match verify_password(config, password, &persisted_user.password, pepper_key_id).await {
+        Ok(true) => {
+            //  Hashes made with an older algorithm, cost or pepper get replaced on the next login
+            let needs_rehash =
+                password_needs_rehash(config, &persisted_user.password, pepper_key_id);
+            Ok(Some((persisted_user, needs_rehash)))
+        }
+        Ok(false) => Ok(None),
+        Err(e) => Err(hashing_error(e)),
     }
 }
 
-async fn change_password<'a>(
+/// Validates the new password against the length limits and the password policy, then hashes and stores it.
+/// The replaced hash goes into the password history, which is trimmed to `history_depth`.
+/// With `check_history` the new password can't match the current one or any in the history.
+async fn change_password(
     conn: &mut PgConnection,
+    config: &AuthConfig,
     mut persisted_user: User,
-    new_password: &String,
-) -> Result<User, ErrorResource<'a>> {
-    let hash_result = hash_password(&new_password);
-    persisted_user.password = hash_result.hash;
-    persisted_user.salt = hash_result.salt;
+    new_password: &str,
+    check_history: bool,
+) -> Result<User, UserLibError> {
+    let credentials = match fetch_user_credentials(conn, &persisted_user.id).await {
+        Ok(credentials) => credentials,
+        Err(e) => {
+            error!("{}", e);
+            return Err(UserLibError::Storage(e));
+        }
+    };
+    let mut personal_information: Vec<&str> = credentials
+        .iter()
+        .map(|credential| credential.credential.as_str())
+        .collect();
+    personal_information.push(&persisted_user.name);
+    let mut error_resources: Vec<ErrorResource> = Vec::new();
+    validate_new_password(
+        &mut error_resources,
+        config,
+        new_password,
+        &personal_information,
+    );
+    validate_password_not_breached(&mut error_resources, config, new_password).await?;
+    if !error_resources.is_empty() {
+        return Err(UserLibError::from_errors(
+            error_resources
+                .into_iter()
+                .map(UserLibError::Validation)
+                .collect(),
+        ));
+    }
+
+    let history_depth = config.password_policy.history_depth;
+    if history_depth > 0 {
+        if check_history
+            && is_password_recently_used(conn, config, &persisted_user, new_password).await?
+        {
+            return Err(UserLibError::Validation(ERROR_PASSWORD_RECENTLY_USED));
+        }
+        if let Err(e) = insert_password_history(
+            conn,
+            &persisted_user.id,
+            &persisted_user.password,
+            persisted_user.pepper_key_id.as_deref(),
+        )
+        .await
+        {
+            error!("{}", e);
+            return Err(UserLibError::Storage(e));
+        }
+        if let Err(e) = trim_password_history(conn, &persisted_user.id, history_depth as i64).await
+        {
+            error!("{}", e);
+            return Err(UserLibError::Storage(e));
+        }
+    }
+
+    (persisted_user.password, persisted_user.pepper_key_id) =
+        match hash_password(config, new_password).await {
+            Ok(password_hash) => password_hash,
+            Err(e) => return Err(hashing_error(e)),
+        };
     match update_user(conn, persisted_user).await {
         Ok(user) => Ok(user),
         Err(error) => {
             error!("{}", error);
-            return Err(("ERROR.DATABASE_ERROR", ""));
+            Err(UserLibError::Storage(error))
         }
     }
 }
+
+/// Whether `password` is the user's current password or one of the last `history_depth` ones.
+/// History entries that can't be verified anymore, like ones made with a removed pepper, are skipped.
+async fn is_password_recently_used(
+    conn: &mut PgConnection,
+    config: &AuthConfig,
+    persisted_user: &User,
+    password: &str,
+) -> Result<bool, UserLibError> {
+    let history = match fetch_password_history(
+        conn,
+        &persisted_user.id,
+        config.password_policy.history_depth as i64,
+    )
+    .await
+    {
+        Ok(history) => history,
+        Err(e) => {
+            error!("{}", e);
+            return Err(UserLibError::Storage(e));
+        }
+    };
+    let current_password = std::iter::once((
+        persisted_user.password.as_str(),
+        persisted_user.pepper_key_id.as_deref(),
+    ));
+    let previous_passwords = history
+        .iter()
+        .map(|entry| (entry.password.as_str(), entry.pepper_key_id.as_deref()));
+    for (password_hash, pepper_key_id) in current_password.chain(previous_passwords) {
+        match verify_password(config, password, password_hash, pepper_key_id).await {
+            Ok(true) => return Ok(true),
+            Ok(false) => {}
+            Err(e) => warn!(
+                "Skipping password history entry of user {}: {}",
+                persisted_user.id, e
+            ),
+        }
+    }
+    Ok(false)
+}
+
+/// Replaces an outdated hash with one made with the current settings.
+/// A hashing failure keeps the old hash, since it still verifies.
+async fn rehash_password(
+    conn: &mut PgConnection,
+    config: &AuthConfig,
+    mut persisted_user: User,
+    password: &str,
+) -> Result<(), UserLibError> {
+    (persisted_user.password, persisted_user.pepper_key_id) =
+        match hash_password(config, password).await {
+            Ok(password_hash) => password_hash,
+            Err(e) => {
+                warn!(
+                    "Couldn't rehash the password of user {}: {}",
+                    persisted_user.id, e
+                );
+                return Ok(());
+            }
+        };
+    match update_user(conn, persisted_user).await {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            error!("{}", e);
+            Err(UserLibError::Storage(e))
+        }
+    }
+}
+
+fn hashing_error(error: PasswordHashError) -> UserLibError {
+    error!("{}", error);
+    UserLibError::Internal {
+        resource: ERROR_HASHING_PASSWORD,
+        source: Some(Box::new(error)),
+    }
+}
@@ -0,0 +1,315 @@
+use crate::config::auth_config::AuthConfig;
+use crate::domain::credential::{Credential, CredentialCollision, CredentialType};
+use crate::domain::error::UserLibError;
+use crate::domain::token::{Session, Token};
+use crate::domain::user::User;
+use crate::dto::credential::CredentialDto;
+use crate::dto::token::{AuthenticateUserDto, RefreshAuthTokenForUserDto};
+use crate::dto::users::{UserLoginPayload, UserRegisterPayload, UserResetPasswordPayload};
+use crate::dto::verification::ConfirmCredentialVerificationDto;
+use crate::notification::notification_sender::NotificationSender;
+use crate::resources::error_messages::{
+    ERROR_EXPIRED_VERIFICATION_CODE, ERROR_INVALID_VERIFICATION_CODE, ERROR_REUSED_REFRESH_TOKEN,
+    ERROR_SENDING_NOTIFICATION, ERROR_TOO_MANY_VERIFICATION_ATTEMPTS,
+};
+use crate::service::{credential, token, user, verification};
+use chrono::{DateTime, Utc};
+use log::error;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+/// Cloneable handle that owns the connection pool and the configuration.
+/// Every operation runs inside its own transaction, which is committed when the operation succeeds.
+/// Use the functions in `service::user` and `service::token` directly to compose your own transactions.
+#[derive(Debug, Clone)]
+pub struct UserService {
+    pool: PgPool,
+    config: Arc<AuthConfig>,
+    notification_sender: Option<Arc<dyn NotificationSender>>,
+}
+
+impl UserService {
+    pub fn new(pool: PgPool, config: AuthConfig) -> Self {
+        UserService {
+            pool,
+            config: Arc::new(config),
+            notification_sender: None,
+        }
+    }
+
+    /// Sender of the credential verification codes. Needed by the verification methods.
+    pub fn with_notification_sender(
+        mut self,
+        notification_sender: impl NotificationSender + 'static,
+    ) -> Self {
+        self.notification_sender = Some(Arc::new(notification_sender));
+        self
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    pub fn config(&self) -> &AuthConfig {
+        &self.config
+    }
+
+    pub async fn register_user(&self, user: UserRegisterPayload) -> Result<Token, UserLibError> {
+        let mut transaction = self.begin().await?;
+        let result = user::register_user(&mut transaction, &self.config, user).await;
+        commit_if_ok(transaction, result).await
+    }
+
+    pub async fn authenticate_user(&self, user: AuthenticateUserDto) -> Result<User, UserLibError> {
+        let mut transaction = self.begin().await?;
+        let result = user::authenticate_user(&mut transaction, &self.config, user).await;
+        commit_if_ok(transaction, result).await
+    }
+
+    /// A reused refresh token revokes its session and returns an error,
+    /// so this commits on `ERROR_REUSED_REFRESH_TOKEN` as well.
+    pub async fn refresh_auth_token(
+        &self,
+        user: RefreshAuthTokenForUserDto,
+    ) -> Result<Token, UserLibError> {
+        let mut transaction = self.begin().await?;
+        let result = user::refresh_auth_token(&mut transaction, &self.config, user).await;
+        match result {
+            Err(UserLibError::Token(resource)) if resource == ERROR_REUSED_REFRESH_TOKEN => {
+                commit_if_ok(transaction, Ok(())).await?;
+                Err(UserLibError::Token(resource))
+            }
+            result => commit_if_ok(transaction, result).await,
+        }
+    }
+
+    pub async fn password_login(&self, user: UserLoginPayload) -> Result<Token, UserLibError> {
+        let mut transaction = self.begin().await?;
+        let result = user::password_login(&mut transaction, &self.config, user).await;
+        commit_if_ok(transaction, result).await
+    }
+
+    pub async fn reset_password(
+        &self,
+        user: UserResetPasswordPayload,
+    ) -> Result<User, UserLibError> {
+        let mut transaction = self.begin().await?;
+        let result = user::reset_password(&mut transaction, &self.config, user).await;
+        commit_if_ok(transaction, result).await
+    }
+
+    /// ## This resets a user's password without validating the user's identity!
+    /// Don't expose this to any public endpoint!! The new password is still checked against the length limits,
+    /// the `PasswordPolicy` and the breached password list.
+    pub async fn force_reset_password(
+        &self,
+        user_id: &i32,
+        new_password: String,
+    ) -> Result<User, UserLibError> {
+        let mut transaction = self.begin().await?;
+        let result =
+            user::force_reset_password(&mut transaction, &self.config, user_id, new_password).await;
+        commit_if_ok(transaction, result).await
+    }
+
+    pub async fn get_user_credentials(
+        &self,
+        user: AuthenticateUserDto,
+    ) -> Result<Vec<Credential>, UserLibError> {
+        let mut transaction = self.begin().await?;
+        let result = user::get_user_credentials(&mut transaction, &self.config, user).await;
+        commit_if_ok(transaction, result).await
+    }
+
+    pub async fn add_credential(
+        &self,
+        user: AuthenticateUserDto,
+        credential: CredentialDto,
+    ) -> Result<Credential, UserLibError> {
+        let mut transaction = self.begin().await?;
+        let result =
+            credential::add_credential(&mut transaction, &self.config, user, credential).await;
+        commit_if_ok(transaction, result).await
+    }
+
+    pub async fn update_credential(
+        &self,
+        user: AuthenticateUserDto,
+        credential: CredentialDto,
+    ) -> Result<Credential, UserLibError> {
+        let mut transaction = self.begin().await?;
+        let result =
+            credential::update_credential(&mut transaction, &self.config, user, credential).await;
+        commit_if_ok(transaction, result).await
+    }
+
+    pub async fn remove_credential(
+        &self,
+        user: AuthenticateUserDto,
+        credential_type: CredentialType,
+    ) -> Result<Credential, UserLibError> {
+        let mut transaction = self.begin().await?;
+        let result =
+            credential::remove_credential(&mut transaction, &self.config, user, credential_type)
+                .await;
+        commit_if_ok(transaction, result).await
+    }
+
+    /// See `service::credential::find_credential_collisions`.
+    pub async fn find_credential_collisions(
+        &self,
+    ) -> Result<Vec<CredentialCollision>, UserLibError> {
+        let mut transaction = self.begin().await?;
+        let result = credential::find_credential_collisions(&mut transaction, &self.config).await;
+        commit_if_ok(transaction, result).await
+    }
+
+    /// See `service::credential::canonicalize_persisted_credentials`.
+    pub async fn canonicalize_persisted_credentials(
+        &self,
+    ) -> Result<Vec<CredentialCollision>, UserLibError> {
+        let mut transaction = self.begin().await?;
+        let result =
+            credential::canonicalize_persisted_credentials(&mut transaction, &self.config).await;
+        commit_if_ok(transaction, result).await
+    }
+
+    pub async fn request_credential_verification(
+        &self,
+        user: AuthenticateUserDto,
+        credential_type: CredentialType,
+    ) -> Result<(), UserLibError> {
+        let notification_sender = self.get_notification_sender()?;
+        let mut transaction = self.begin().await?;
+        let result = verification::request_credential_verification(
+            &mut transaction,
+            &self.config,
+            notification_sender,
+            user,
+            credential_type,
+        )
+        .await;
+        commit_if_ok(transaction, result).await
+    }
+
+    pub async fn send_credential_verification(
+        &self,
+        user_id: &i32,
+        credential_type: CredentialType,
+    ) -> Result<(), UserLibError> {
+        let notification_sender = self.get_notification_sender()?;
+        let mut transaction = self.begin().await?;
+        let result = verification::send_credential_verification(
+            &mut transaction,
+            &self.config,
+            notification_sender,
+            user_id,
+            credential_type,
+        )
+        .await;
+        commit_if_ok(transaction, result).await
+    }
+
+    /// Wrong, expired and exhausted codes are still written (attempt counter, removal),
+    /// so this commits on those errors as well.
+    pub async fn confirm_credential_verification(
+        &self,
+        payload: ConfirmCredentialVerificationDto,
+    ) -> Result<Credential, UserLibError> {
+        let mut transaction = self.begin().await?;
+        let result =
+            verification::confirm_credential_verification(&mut transaction, &self.config, payload)
+                .await;
+        match result {
+            Err(UserLibError::Token(resource))
+                if resource == ERROR_INVALID_VERIFICATION_CODE
+                    || resource == ERROR_EXPIRED_VERIFICATION_CODE
+                    || resource == ERROR_TOO_MANY_VERIFICATION_ATTEMPTS =>
+            {
+                commit_if_ok(transaction, Ok(())).await?;
+                Err(UserLibError::Token(resource))
+            }
+            result => commit_if_ok(transaction, result).await,
+        }
+    }
+
+    pub async fn logout(&self, user: AuthenticateUserDto) -> Result<Token, UserLibError> {
+        let mut transaction = self.begin().await?;
+        let result = token::logout(&mut transaction, user).await;
+        commit_if_ok(transaction, result).await
+    }
+
+    pub async fn logout_all_sessions(&self, user_id: &i32) -> Result<Vec<Token>, UserLibError> {
+        let mut transaction = self.begin().await?;
+        let result = token::logout_all_sessions(&mut transaction, user_id).await;
+        commit_if_ok(transaction, result).await
+    }
+
+    pub async fn list_sessions(&self, user_id: &i32) -> Result<Vec<Session>, UserLibError> {
+        let mut transaction = self.begin().await?;
+        let result = token::list_sessions(&mut transaction, user_id).await;
+        commit_if_ok(transaction, result).await
+    }
+
+    pub async fn revoke_session(
+        &self,
+        user_id: &i32,
+        token_id: &i32,
+    ) -> Result<Token, UserLibError> {
+        let mut transaction = self.begin().await?;
+        let result = token::revoke_session(&mut transaction, user_id, token_id).await;
+        commit_if_ok(transaction, result).await
+    }
+
+    pub async fn purge_expired_tokens(
+        &self,
+        older_than: DateTime<Utc>,
+    ) -> Result<u64, UserLibError> {
+        let mut transaction = self.begin().await?;
+        let result = token::purge_expired_tokens(&mut transaction, &self.config, older_than).await;
+        commit_if_ok(transaction, result).await
+    }
+
+    pub fn spawn_expired_token_janitor(&self, interval: std::time::Duration) -> JoinHandle<()> {
+        token::spawn_expired_token_janitor(self.pool.clone(), (*self.config).clone(), interval)
+    }
+
+    fn get_notification_sender(&self) -> Result<&dyn NotificationSender, UserLibError> {
+        match &self.notification_sender {
+            Some(notification_sender) => Ok(notification_sender.as_ref()),
+            None => {
+                error!("No notification sender set. Use UserService::with_notification_sender.");
+                Err(UserLibError::Internal {
+                    resource: ERROR_SENDING_NOTIFICATION,
+                    source: None,
+                })
+            }
+        }
+    }
+
+    async fn begin(&self) -> Result<Transaction<'static, Postgres>, UserLibError> {
+        match self.pool.begin().await {
+            Ok(transaction) => Ok(transaction),
+            Err(e) => {
+                error!("{}", e);
+                Err(UserLibError::Storage(e))
+            }
+        }
+    }
+}
+
+/// Commits the transaction if the operation succeeded. Otherwise it's dropped, which rolls it back.
+async fn commit_if_ok<T>(
+    transaction: Transaction<'static, Postgres>,
+    result: Result<T, UserLibError>,
+) -> Result<T, UserLibError> {
+    let value = result?;
+    match transaction.commit().await {
+        Ok(()) => Ok(value),
+        Err(e) => {
+            error!("{}", e);
+            Err(UserLibError::Storage(e))
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use crate::config::auth_config::AuthConfig;
+use crate::dao::credential::{fetch_user_credentials, set_credential_validated};
+use crate::dao::credential_verification::{
+    claim_credential_verification_attempt, delete_credential_verification,
+    get_credential_verification, upsert_credential_verification,
+};
+use crate::domain::credential::{Credential, CredentialType};
+use crate::domain::credential_verification::CredentialVerification;
+use crate::domain::error::{PasswordHashError, UserLibError};
+use crate::dto::token::AuthenticateUserDto;
+use crate::dto::verification::ConfirmCredentialVerificationDto;
+use crate::notification::notification_sender::{NotificationSender, VerificationNotification};
+use crate::resources::error_messages::{
+    ERROR_CREDENTIAL_DOES_NOT_EXIST, ERROR_CREDENTIAL_NOT_VERIFIABLE,
+    ERROR_EXPIRED_VERIFICATION_CODE, ERROR_HASHING_VERIFICATION_CODE,
+    ERROR_INVALID_VERIFICATION_CODE, ERROR_SENDING_NOTIFICATION, ERROR_TOKEN_NOT_CREATED,
+    ERROR_TOO_MANY_VERIFICATION_ATTEMPTS,
+};
+use crate::service::user::authenticate_user;
+use crate::utils::hasher::{
+    generate_numeric_code, generate_url_safe_token, hash_verification_code,
+    verify_verification_code,
+};
+use chrono::{Duration, Utc};
+use log::{debug, error, warn};
+use sqlx::PgConnection;
+
+/// Sends a new verification code to the authenticated user's credential of `credential_type`.
+/// Emails get a URL-safe token meant for a link, phone numbers a short numeric code.
+/// Any code sent before for that credential stops working.
+pub async fn request_credential_verification(
+    conn: &mut PgConnection,
+    config: &AuthConfig,
+    notification_sender: &dyn NotificationSender,
+    user: AuthenticateUserDto,
+    credential_type: CredentialType,
+) -> Result<(), UserLibError> {
+    let persisted_user = authenticate_user(conn, config, user).await?;
+    send_credential_verification(
+        conn,
+        config,
+        notification_sender,
+        &persisted_user.id,
+        credential_type,
+    )
+    .await
+}
+
+/// Same as `request_credential_verification` without authenticating the user, like right after `register_user`.
+pub async fn send_credential_verification(
+    conn: &mut PgConnection,
+    config: &AuthConfig,
+    notification_sender: &dyn NotificationSender,
+    user_id: &i32,
+    credential_type: CredentialType,
+) -> Result<(), UserLibError> {
+    let code_result = match credential_type {
+        CredentialType::Email => generate_url_safe_token(),
+        CredentialType::PhoneNumber => generate_numeric_code(config.verification_code_length),
+        CredentialType::Username => {
+            return Err(UserLibError::Validation(ERROR_CREDENTIAL_NOT_VERIFIABLE))
+        }
+    };
+    let code = match code_result {
+        Ok(code) => code,
+        Err(e) => {
+            error!("{}", e);
+            return Err(UserLibError::Internal {
+                resource: ERROR_TOKEN_NOT_CREATED,
+                source: Some(Box::new(e)),
+            });
+        }
+    };
+    let credential = get_user_credential(conn, user_id, &credential_type).await?;
+    let code_hash = match hash_verification_code(config, &code).await {
+        Ok(code_hash) => code_hash,
+        Err(e) => return Err(hashing_error(e)),
+    };
+    let expires_at =
+        Utc::now() + Duration::milliseconds(config.verification_code_expiration_time_millis);
+    if let Err(e) = upsert_credential_verification(
+        conn,
+        user_id,
+        &credential_type,
+        &credential.credential,
+        &code_hash,
+        expires_at,
+    )
+    .await
+    {
+        error!("{}", e);
+        return Err(UserLibError::Storage(e));
+    }
+
+    let notification = VerificationNotification {
+        user_id: *user_id,
+        credential_type,
+        credential: credential.credential,
+        code,
+        expires_at,
+    };
+    match notification_sender.send_verification(notification).await {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            error!("{}", e);
+            Err(UserLibError::Internal {
+                resource: ERROR_SENDING_NOTIFICATION,
+                source: Some(Box::new(e)),
+            })
+        }
+    }
+}
+
+/// Marks a credential as validated if `code` is the last one sent to it, hasn't expired and wasn't used yet.
+/// Wrong codes count towards `config.max_verification_attempts`; once they're used up, or the code expires,
+/// a new one has to be requested. Those failures are still written to `conn`, so commit the transaction
+/// even when `ERROR_INVALID_VERIFICATION_CODE`, `ERROR_EXPIRED_VERIFICATION_CODE` or
+/// `ERROR_TOO_MANY_VERIFICATION_ATTEMPTS` comes back.
+pub async fn confirm_credential_verification(
+    conn: &mut PgConnection,
+    config: &AuthConfig,
+    payload: ConfirmCredentialVerificationDto,
+) -> Result<Credential, UserLibError> {
+    let verification =
+        match get_credential_verification(conn, &payload.user_id, &payload.credential_type).await {
+            Ok(Some(verification)) => verification,
+            Ok(None) => return Err(UserLibError::Token(ERROR_INVALID_VERIFICATION_CODE)),
+            Err(e) => {
+                error!("{}", e);
+                return Err(UserLibError::Storage(e));
+            }
+        };
+
+    if verification.expires_at < Utc::now() {
+        debug!("Expired verification code: {:?}", verification);
+        remove_verification(conn, &verification).await?;
+        return Err(UserLibError::Token(ERROR_EXPIRED_VERIFICATION_CODE));
+    }
+    //  Claimed before comparing, so parallel guesses can't all get in under the limit
+    let verification = match claim_credential_verification_attempt(
+        conn,
+        &verification.id,
+        config.max_verification_attempts,
+    )
+    .await
+    {
+        Ok(Some(verification)) => verification,
+        Ok(None) => {
+            remove_verification(conn, &verification).await?;
+            return Err(UserLibError::Token(ERROR_TOO_MANY_VERIFICATION_ATTEMPTS));
+        }
+        Err(e) => {
+            error!("{}", e);
+            return Err(UserLibError::Storage(e));
+        }
+    };
+
+    let code_matches =
+        match verify_verification_code(config, &payload.code, &verification.code).await {
+            Ok(code_matches) => code_matches,
+            Err(e) => return Err(hashing_error(e)),
+        };
+    if !code_matches {
+        warn!(
+            "Wrong verification code for user {} ({} attempts).",
+            verification.user_id, verification.attempts
+        );
+        return Err(UserLibError::Token(ERROR_INVALID_VERIFICATION_CODE));
+    }
+
+    //  Single use, whatever happens next
+    remove_verification(conn, &verification).await?;
+    match set_credential_validated(
+        conn,
+        &verification.user_id,
+        &verification.credential_type,
+        &verification.credential,
+    )
+    .await
+    {
+        Ok(Some(credential)) => Ok(credential),
+        //  The credential changed after the code was sent
+        Ok(None) => Err(UserLibError::Token(ERROR_INVALID_VERIFICATION_CODE)),
+        Err(e) => {
+            error!("{}", e);
+            Err(UserLibError::Storage(e))
+        }
+    }
+}
+
+async fn get_user_credential(
+    conn: &mut PgConnection,
+    user_id: &i32,
+    credential_type: &CredentialType,
+) -> Result<Credential, UserLibError> {
+    match fetch_user_credentials(conn, user_id).await {
+        Ok(credentials) => match credentials
+            .into_iter()
+            .find(|credential| credential.credential_type == *credential_type)
+        {
+            Some(credential) => Ok(credential),
+            None => Err(UserLibError::Authentication(
+                ERROR_CREDENTIAL_DOES_NOT_EXIST,
+            )),
+        },
+        Err(e) => {
+            error!("{}", e);
+            Err(UserLibError::Storage(e))
+        }
+    }
+}
+
+async fn remove_verification(
+    conn: &mut PgConnection,
+    verification: &CredentialVerification,
+) -> Result<(), UserLibError> {
+    match delete_credential_verification(conn, &verification.id).await {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            error!("{}", e);
+            Err(UserLibError::Storage(e))
+        }
+    }
+}
+
+fn hashing_error(error: PasswordHashError) -> UserLibError {
+    error!("{}", error);
+    UserLibError::Internal {
+        resource: ERROR_HASHING_VERIFICATION_CODE,
+        source: Some(Box::new(error)),
+    }
+}
@@ -0,0 +1,219 @@
+use crate::config::auth_config::AuthConfig;
+use crate::domain::credential::CredentialType;
+use crate::dto::credential::CredentialDto;
+use phonenumber::country;
+use phonenumber::Mode;
+use unicode_normalization::UnicodeNormalization;
+
+/// The form a credential is stored and looked up in, so the same email, phone number or username
+/// written differently always finds the same row:
+/// - Emails are lowercased, and their domain converted to its IDNA ASCII form (`bob@bücher.de` becomes `bob@xn--bcher-kva.de`).
+/// - Phone numbers are formatted as E.164 (`+18095551234`), using `config.default_phone_region` for numbers without a country code.
+/// - Usernames are Unicode NFKC normalized.
+///
+/// Values that can't be parsed are only trimmed and left for validation to reject.
+pub fn canonicalize_credential(
+    config: &AuthConfig,
+    credential: &str,
+    credential_type: &CredentialType,
+) -> String {
+    let credential = credential.trim();
+    match credential_type {
+        CredentialType::Email => canonicalize_email(credential),
+        CredentialType::PhoneNumber => {
+            canonicalize_phone_number(credential, config.default_phone_region.as_deref())
+        }
+        CredentialType::Username => credential.nfkc().collect(),
+    }
+}
+
+pub fn canonicalize_credential_dto(
+    config: &AuthConfig,
+    credential_dto: CredentialDto,
+) -> CredentialDto {
+    CredentialDto {
+        credential: canonicalize_credential(
+            config,
+            &credential_dto.credential,
+            &credential_dto.credential_type,
+        ),
+        credential_type: credential_dto.credential_type,
+    }
+}
+
+fn canonicalize_email(email: &str) -> String {
+    let email = email.to_lowercase();
+    let (local_part, domain) = match email.rsplit_once('@') {
+        Some(parts) => parts,
+        None => return email,
+    };
+    match idna::domain_to_ascii(domain) {
+        Ok(ascii_domain) if !ascii_domain.is_empty() => format!("{}@{}", local_part, ascii_domain),
+        _ => email,
+    }
+}
+
+fn canonicalize_phone_number(phone_number: &str, default_region: Option<&str>) -> String {
+    let region = default_region.and_then(|region| region.parse::<country::Id>().ok());
+    match phonenumber::parse(region, phone_number) {
+        Ok(parsed) if phonenumber::is_valid(&parsed) => {
+            parsed.format().mode(Mode::E164).to_string()
+        }
+        _ => phone_number.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::error_messages::ErrorResource;
+    use crate::validation::user_validator::validate_credential;
+
+    fn canonicalize(
+        config: &AuthConfig,
+        credential: &str,
+        credential_type: CredentialType,
+    ) -> String {
+        canonicalize_credential(config, credential, &credential_type)
+    }
+
+    #[test]
+    fn emails_are_lowercased_and_trimmed() {
+        let config = AuthConfig::default();
+        assert_eq!(
+            canonicalize(&config, " Bob@Example.COM ", CredentialType::Email),
+            "bob@example.com"
+        );
+        assert_eq!(
+            canonicalize(&config, "bob@example.com", CredentialType::Email),
+            canonicalize(&config, "BOB@EXAMPLE.COM", CredentialType::Email)
+        );
+    }
+
+    #[test]
+    fn email_domains_are_converted_to_idna_ascii() {
+        let config = AuthConfig::default();
+        assert_eq!(
+            canonicalize(&config, "bob@Bücher.de", CredentialType::Email),
+            "bob@xn--bcher-kva.de"
+        );
+        assert_eq!(
+            canonicalize(&config, "bob@xn--bcher-kva.de", CredentialType::Email),
+            "bob@xn--bcher-kva.de"
+        );
+    }
+
+    #[test]
+    fn emails_keep_everything_before_the_last_at_sign() {
+        let config = AuthConfig::default();
+        assert_eq!(
+            canonicalize(&config, "\"a@b\"@Example.com", CredentialType::Email),
+            "\"a@b\"@example.com"
+        );
+        assert_eq!(
+            canonicalize(&config, "Not An Email", CredentialType::Email),
+            "not an email"
+        );
+    }
+
+    #[test]
+    fn phone_numbers_with_a_country_code_become_e164_without_a_region() {
+        let config = AuthConfig::default();
+        assert_eq!(
+            canonicalize(&config, "+1 (809) 555-1234", CredentialType::PhoneNumber),
+            "+18095551234"
+        );
+        assert_eq!(
+            canonicalize(&config, "+44 20 7946 0958", CredentialType::PhoneNumber),
+            "+442079460958"
+        );
+    }
+
+    #[test]
+    fn phone_numbers_without_a_country_code_need_a_region() {
+        let without_region = AuthConfig::default();
+        assert_eq!(
+            canonicalize(
+                &without_region,
+                " 809-555-1234 ",
+                CredentialType::PhoneNumber
+            ),
+            "809-555-1234"
+        );
+        let with_region = AuthConfig::builder().default_phone_region("do").build();
+        assert_eq!(
+            canonicalize(&with_region, "809-555-1234", CredentialType::PhoneNumber),
+            "+18095551234"
+        );
+        let with_other_region = AuthConfig::builder().default_phone_region("GB").build();
+        assert_eq!(
+            canonicalize(
+                &with_other_region,
+                "020 7946 0958",
+                CredentialType::PhoneNumber
+            ),
+            "+442079460958"
+        );
+    }
+
+    #[test]
+    fn invalid_phone_numbers_are_only_trimmed() {
+        let config = AuthConfig::builder().default_phone_region("US").build();
+        assert_eq!(
+            canonicalize(&config, " 12 ", CredentialType::PhoneNumber),
+            "12"
+        );
+        assert_eq!(
+            canonicalize(&config, "not a number", CredentialType::PhoneNumber),
+            "not a number"
+        );
+    }
+
+    #[test]
+    fn longest_e164_numbers_pass_validation() {
+        let config = AuthConfig::default();
+        let mut error_resources: Vec<ErrorResource> = Vec::new();
+        validate_credential(
+            &mut error_resources,
+            &config,
+            "+123456789012345",
+            &CredentialType::PhoneNumber,
+        );
+        assert!(error_resources.is_empty());
+    }
+
+    #[test]
+    fn usernames_are_nfkc_normalized() {
+        let config = AuthConfig::default();
+        assert_eq!(
+            canonicalize(&config, "ｆｏｏ①", CredentialType::Username),
+            "foo1"
+        );
+        //  Composed and decomposed forms of "José"
+        assert_eq!(
+            canonicalize(&config, "Jos\u{e9}", CredentialType::Username),
+            canonicalize(&config, "Jose\u{301}", CredentialType::Username)
+        );
+        //  Usernames keep their case
+        assert_eq!(
+            canonicalize(&config, " Bob ", CredentialType::Username),
+            "Bob"
+        );
+    }
+
+    #[test]
+    fn canonicalizing_twice_changes_nothing() {
+        let config = AuthConfig::builder().default_phone_region("DO").build();
+        for (credential, credential_type) in [
+            ("Bob@Bücher.de", CredentialType::Email),
+            ("809-555-1234", CredentialType::PhoneNumber),
+            ("ｆｏｏ", CredentialType::Username),
+        ] {
+            let once = canonicalize_credential(&config, credential, &credential_type);
+            assert_eq!(
+                canonicalize_credential(&config, &once, &credential_type),
+                once
+            );
+        }
+    }
+}
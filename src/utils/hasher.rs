@@ -1,17 +1,18 @@
-use crate::dto::hash_result::HashResult;
-use data_encoding::BASE64;
+use crate::config::auth_config::{AuthConfig, PasswordPepper};
+use crate::domain::error::{PasswordHashError, TokenGenerationError};
+use crate::utils::password_hasher::{
+    Argon2idHasher, BcryptHasher, PasswordHasher, Pbkdf2Hasher, ScryptHasher,
+};
+use data_encoding::{BASE64, BASE64URL_NOPAD};
 use ring::{
-    digest, pbkdf2,
+    constant_time, digest, hmac,
     rand::{SecureRandom, SystemRandom},
 };
-use std::num::NonZeroU32;
-use tokio::task::JoinError;
-
-const SALT_ROUNDS: u32 = 1000;
 
+/// Generates `amount` random base64 tokens. Fails instead of returning empty tokens.
 pub(crate) async fn generate_multiple_random_token_with_rng(
     amount: u8,
-) -> Result<Vec<String>, JoinError> {
+) -> Result<Vec<String>, TokenGenerationError> {
     //  Get a new instance of a Random Number Generator
     let rng = SystemRandom::new();
 
@@ -22,83 +23,313 @@ pub(crate) async fn generate_multiple_random_token_with_rng(
         let future_token = async move {
             let mut token_arr = [0u8; digest::SHA512_OUTPUT_LEN];
             match cloned_rng.fill(&mut token_arr) {
-                Ok(()) => BASE64.encode(&token_arr),
-                Err(_e) => {
-                    panic!("Failed to generate random token for some reason.")
-                }
+                Ok(()) => Ok(BASE64.encode(&token_arr)),
+                Err(_e) => Err(TokenGenerationError::Random),
             }
         };
         tokens.push(tokio::spawn(future_token));
     }
 
     let all_tokens = futures_util::future::join_all(tokens).await;
-    let all_tokens_solved: Vec<String> = all_tokens
-        .into_iter()
-        .map(|result| match result {
-            Ok(string) => string,
-            Err(_e) => "".to_string(),
-        })
-        .rev()
-        .collect();
+    let mut all_tokens_solved: Vec<String> = Vec::with_capacity(all_tokens.len());
+    for result in all_tokens.into_iter().rev() {
+        match result {
+            Ok(Ok(token)) if !token.is_empty() => all_tokens_solved.push(token),
+            Ok(Ok(_)) => return Err(TokenGenerationError::Empty),
+            Ok(Err(e)) => return Err(e),
+            Err(e) => return Err(TokenGenerationError::Join(e)),
+        }
+    }
 
     Ok(all_tokens_solved)
 }
 
-pub(crate) fn hash_password_with_existing_salt(
-    password: &String,
-    input_salt: &String,
-) -> HashResult {
-    //  Get output length from a sha512 hash
-    const CREDENTIAL_LEN: usize = digest::SHA512_OUTPUT_LEN;
-    let n_iter = NonZeroU32::new(SALT_ROUNDS).unwrap();
-
-    let salt = BASE64.decode(input_salt.as_bytes()).unwrap();
-
-    //  Create empty 64-bit byte array for the hash + salt
-    let mut pbkdf2_hash = [0u8; CREDENTIAL_LEN];
-
-    //  Fills byte array with hashed values
-    pbkdf2::derive(
-        pbkdf2::PBKDF2_HMAC_SHA512,
-        n_iter,
-        &salt,
-        password.as_bytes(),
-        &mut pbkdf2_hash,
-    );
-
-    //  Return an object containing the salt and the hash
-    HashResult::new(BASE64.encode(&salt), BASE64.encode(&pbkdf2_hash))
+/// Random numeric code of `length` digits, for codes people type in.
+pub(crate) fn generate_numeric_code(length: usize) -> Result<String, TokenGenerationError> {
+    let rng = SystemRandom::new();
+    let mut code = String::with_capacity(length);
+    let mut random_byte = [0u8; 1];
+    while code.len() < length {
+        if rng.fill(&mut random_byte).is_err() {
+            return Err(TokenGenerationError::Random);
+        }
+        //  Rejection sampling, so every digit is equally likely
+        if random_byte[0] < 250 {
+            code.push(char::from(b'0' + random_byte[0] % 10));
+        }
+    }
+    if code.is_empty() {
+        return Err(TokenGenerationError::Empty);
+    }
+    Ok(code)
 }
 
-pub(crate) fn hash_password(password: &String) -> HashResult {
-    //  Get output length from a sha512 hash
-    const CREDENTIAL_LEN: usize = digest::SHA512_OUTPUT_LEN;
-    let n_iter = NonZeroU32::new(SALT_ROUNDS).unwrap();
-    let rng = SystemRandom::new();
+/// Random URL-safe token, for codes that go in a link.
+pub(crate) fn generate_url_safe_token() -> Result<String, TokenGenerationError> {
+    let mut token_arr = [0u8; digest::SHA256_OUTPUT_LEN];
+    match SystemRandom::new().fill(&mut token_arr) {
+        Ok(()) => Ok(BASE64URL_NOPAD.encode(&token_arr)),
+        Err(_e) => Err(TokenGenerationError::Random),
+    }
+}
 
-    //  Create empty 64-byte array for the salt
-    let mut salt = [0u8; CREDENTIAL_LEN];
+/// Hashes a password with the configured hasher, returning a self-describing hash string
+/// and the key id of the pepper mixed into it, if any.
+/// Runs on a blocking thread once one of `config.hashing_permits` is free.
+pub(crate) async fn hash_password(
+    config: &AuthConfig,
+    password: &str,
+) -> Result<(String, Option<String>), PasswordHashError> {
+    let password = password.to_string();
+    run_hashing_job(config, move |config| {
+        hash_password_blocking(config, &password)
+    })
+    .await
+}
 
-    //  Fill array with random-generated salt
-    match rng.fill(&mut salt) {
-        Ok(()) => {}
-        Err(_e) => {
-            panic!("Failed to generate random salt for some reason.")
+/// Checks a password against a stored hash on a blocking thread, see `verify_password_blocking`.
+pub(crate) async fn verify_password(
+    config: &AuthConfig,
+    password: &str,
+    hash: &str,
+    pepper_key_id: Option<&str>,
+) -> Result<bool, PasswordHashError> {
+    let password = password.to_string();
+    let hash = hash.to_string();
+    let pepper_key_id = pepper_key_id.map(str::to_string);
+    run_hashing_job(config, move |config| {
+        verify_password_blocking(config, &password, &hash, pepper_key_id.as_deref())
+    })
+    .await
+}
+
+/// Salted and stretched hash of a verification code, made with the configured password hasher.
+/// Numeric codes only have a million values, so a plain digest would give them away to anyone who can read the database.
+pub(crate) async fn hash_verification_code(
+    config: &AuthConfig,
+    code: &str,
+) -> Result<String, PasswordHashError> {
+    let code = code.to_string();
+    run_hashing_job(config, move |config| {
+        config.password_hasher.hash_password(code.as_bytes())
+    })
+    .await
+}
+
+/// Checks a verification code against the hash stored by `hash_verification_code`.
+pub(crate) async fn verify_verification_code(
+    config: &AuthConfig,
+    code: &str,
+    code_hash: &str,
+) -> Result<bool, PasswordHashError> {
+    let code = code.to_string();
+    let code_hash = code_hash.to_string();
+    run_hashing_job(config, move |config| {
+        verify_password_blocking(config, &code, &code_hash, None)
+    })
+    .await
+}
+
+/// Runs a CPU-heavy hashing job on tokio's blocking threads, waiting for a free hashing permit first
+/// so a burst of logins queues up instead of taking over every thread.
+async fn run_hashing_job<T, F>(config: &AuthConfig, job: F) -> Result<T, PasswordHashError>
+where
+    T: Send + 'static,
+    F: FnOnce(&AuthConfig) -> Result<T, PasswordHashError> + Send + 'static,
+{
+    //  Owned by the blocking job, so the slot stays taken until the hash is done
+    //  even if the caller stops waiting for it.
+    let permit = match config.hashing_permits.clone().acquire_owned().await {
+        Ok(permit) => permit,
+        Err(e) => return Err(PasswordHashError(e.to_string())),
+    };
+    let config = config.clone();
+    match tokio::task::spawn_blocking(move || {
+        let result = job(&config);
+        drop(permit);
+        result
+    })
+    .await
+    {
+        Ok(result) => result,
+        Err(e) => Err(PasswordHashError(e.to_string())),
+    }
+}
+
+fn hash_password_blocking(
+    config: &AuthConfig,
+    password: &str,
+) -> Result<(String, Option<String>), PasswordHashError> {
+    let pepper = config.get_current_pepper();
+    let password_hash = config
+        .password_hasher
+        .hash_password(&apply_pepper(pepper, password))?;
+    Ok((password_hash, pepper.map(|pepper| pepper.key_id.clone())))
+}
+
+/// Checks a password against a stored hash produced by any supported algorithm,
+/// with the pepper it was hashed with. The configured hasher is tried first, so custom hashers can take over any format.
+fn verify_password_blocking(
+    config: &AuthConfig,
+    password: &str,
+    hash: &str,
+    pepper_key_id: Option<&str>,
+) -> Result<bool, PasswordHashError> {
+    let pepper = match pepper_key_id {
+        None => None,
+        Some(key_id) => match config.get_pepper(key_id) {
+            Some(pepper) => Some(pepper),
+            None => {
+                return Err(PasswordHashError(format!(
+                    "Unknown pepper key id {}.",
+                    key_id
+                )))
+            }
+        },
+    };
+    let peppered_password = apply_pepper(pepper, password);
+    if config.password_hasher.can_verify(hash) {
+        return config
+            .password_hasher
+            .verify_password(&peppered_password, hash);
+    }
+    let built_in_hashers: [Box<dyn PasswordHasher>; 4] = [
+        Box::new(Argon2idHasher::default()),
+        Box::new(ScryptHasher::default()),
+        Box::new(BcryptHasher::default()),
+        Box::new(Pbkdf2Hasher::default()),
+    ];
+    match built_in_hashers
+        .into_iter()
+        .find(|hasher| hasher.can_verify(hash))
+    {
+        Some(hasher) => hasher.verify_password(&peppered_password, hash),
+        None => Err(PasswordHashError(String::from(
+            "Unsupported password hash format.",
+        ))),
+    }
+}
+
+/// Whether a stored hash was made with another algorithm, other parameters or another pepper
+/// than the current configuration's.
+pub(crate) fn password_needs_rehash(
+    config: &AuthConfig,
+    hash: &str,
+    pepper_key_id: Option<&str>,
+) -> bool {
+    !config.password_hasher.can_verify(hash)
+        || config.password_hasher.needs_rehash(hash)
+        || pepper_key_id
+            != config
+                .get_current_pepper()
+                .map(|pepper| pepper.key_id.as_str())
+}
+
+/// Bytes that actually get hashed: the password itself without a pepper,
+/// or the base64 encoded HMAC-SHA256 of the password keyed with the pepper.
+fn apply_pepper(pepper: Option<&PasswordPepper>, password: &str) -> Vec<u8> {
+    match pepper {
+        None => password.as_bytes().to_vec(),
+        Some(pepper) => {
+            let key = hmac::Key::new(hmac::HMAC_SHA256, &pepper.secret);
+            BASE64
+                .encode(hmac::sign(&key, password.as_bytes()).as_ref())
+                .into_bytes()
         }
     }
+}
+
+/// Digest of a session token as it's stored in the database.
+/// Tokens are 64 random bytes, so a single unsalted SHA-256 round is enough.
+pub(crate) fn hash_token(token: &str) -> String {
+    BASE64.encode(digest::digest(&digest::SHA256, token.as_bytes()).as_ref())
+}
+
+/// Whether `token` is the plaintext of `token_digest`. The digests are compared in constant time.
+pub(crate) fn verify_token(token: &str, token_digest: &str) -> bool {
+    constant_time::verify_slices_are_equal(hash_token(token).as_bytes(), token_digest.as_bytes())
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::password_hasher::tests::legacy_migrated_hash;
+
+    #[test]
+    fn migrated_legacy_hash_verifies_with_the_default_config() {
+        let config = AuthConfig::default();
+        let hash = legacy_migrated_hash("hunter22");
+        assert!(verify_password_blocking(&config, "hunter22", &hash, None).unwrap());
+        assert!(!verify_password_blocking(&config, "hunter23", &hash, None).unwrap());
+        assert!(password_needs_rehash(&config, &hash, None));
+    }
+
+    #[test]
+    fn verify_token_accepts_the_token_of_a_digest() {
+        assert!(verify_token("token", &hash_token("token")));
+    }
 
-    //  Create empty 64-bit byte array for the hash + salt
-    let mut pbkdf2_hash = [0u8; CREDENTIAL_LEN];
+    #[test]
+    fn verify_token_rejects_another_token() {
+        assert!(!verify_token("token", &hash_token("other token")));
+    }
 
-    //  Fills byte array with hashed values
-    pbkdf2::derive(
-        pbkdf2::PBKDF2_HMAC_SHA512,
-        n_iter,
-        &salt,
-        password.as_bytes(),
-        &mut pbkdf2_hash,
-    );
+    #[test]
+    fn verify_token_rejects_digests_of_another_length() {
+        let digest = hash_token("token");
+        assert!(!verify_token("token", &digest[..digest.len() - 1]));
+        assert!(!verify_token("token", &format!("{}=", digest)));
+    }
+
+    #[test]
+    fn verify_token_rejects_an_empty_digest() {
+        assert!(!verify_token("token", ""));
+        assert!(!verify_token("", ""));
+    }
 
-    //  Return an object containing the salt and the hash
-    HashResult::new(BASE64.encode(&salt), BASE64.encode(&pbkdf2_hash))
+    #[tokio::test]
+    async fn verification_codes_are_salted() {
+        let config = AuthConfig::builder()
+            .password_hasher(Pbkdf2Hasher {
+                iterations: std::num::NonZeroU32::new(10).unwrap(),
+            })
+            .build();
+        let first_hash = hash_verification_code(&config, "123456").await.unwrap();
+        let second_hash = hash_verification_code(&config, "123456").await.unwrap();
+        assert_ne!(first_hash, second_hash);
+        assert_ne!(first_hash, hash_token("123456"));
+        assert!(verify_verification_code(&config, "123456", &first_hash)
+            .await
+            .unwrap());
+        assert!(!verify_verification_code(&config, "123457", &first_hash)
+            .await
+            .unwrap());
+    }
+
+    #[test]
+    fn max_concurrent_hashes_is_at_least_one() {
+        let config = AuthConfig::builder().max_concurrent_hashes(0).build();
+        assert_eq!(config.hashing_permits.available_permits(), 1);
+    }
+
+    #[tokio::test]
+    async fn cancelled_hashing_keeps_its_permit_until_the_job_ends() {
+        let config = AuthConfig::builder().max_concurrent_hashes(1).build();
+        let (started_sender, started_receiver) = std::sync::mpsc::channel();
+        let (finish_sender, finish_receiver) = std::sync::mpsc::channel::<()>();
+        let job = run_hashing_job(&config, move |_| {
+            started_sender.send(()).unwrap();
+            finish_receiver.recv().unwrap();
+            Ok(())
+        });
+        //  Cancel the caller once the blocking job is running
+        tokio::select! {
+            _ = job => panic!("The job can't finish before it's told to."),
+            _ = tokio::task::spawn_blocking(move || started_receiver.recv().unwrap()) => {}
+        }
+        assert_eq!(config.hashing_permits.available_permits(), 0);
+        finish_sender.send(()).unwrap();
+        let _permit = config.hashing_permits.acquire().await.unwrap();
+    }
 }
@@ -1 +1,3 @@
+pub mod canonicalization;
 pub mod hasher;
+pub mod password_hasher;
@@ -0,0 +1,432 @@
+use crate::domain::error::PasswordHashError;
+use crate::resources::hashing::{
+    ARGON2_MEMORY_COST_KIB, ARGON2_PARALLELISM, ARGON2_TIME_COST, BCRYPT_COST, PBKDF2_ITERATIONS,
+    SALT_LENGTH, SCRYPT_BLOCK_SIZE, SCRYPT_LOG_N, SCRYPT_PARALLELISM,
+};
+use argon2::password_hash::{
+    Error as PhcError, PasswordHash, PasswordHasher as PhcHasher, PasswordVerifier, SaltString,
+};
+use argon2::{Algorithm, Argon2, Params, Version};
+use data_encoding::BASE64_NOPAD;
+use ring::{
+    digest, pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use scrypt::Scrypt;
+use std::fmt::Debug;
+use std::num::NonZeroU32;
+
+/// A password hashing algorithm. Hashes are self-describing strings holding the algorithm,
+/// its parameters, the salt and the hash, in PHC string format (`$argon2id$v=19$m=...`).
+/// Bcrypt uses its own modular crypt format (`$2b$12$...`), which every bcrypt implementation understands.
+pub trait PasswordHasher: Debug + Send + Sync {
+    /// Whether `hash` was produced by this algorithm, so this hasher can verify it.
+    fn can_verify(&self, hash: &str) -> bool;
+
+    /// Hashes `password` with a new random salt.
+    fn hash_password(&self, password: &[u8]) -> Result<String, PasswordHashError>;
+
+    /// Checks `password` against `hash`, using the parameters stored in `hash`.
+    fn verify_password(&self, password: &[u8], hash: &str) -> Result<bool, PasswordHashError>;
+
+    /// Whether `hash` was made by this algorithm with other parameters than the current ones,
+    /// so it should be replaced by a new hash the next time the plaintext is known.
+    fn needs_rehash(&self, _hash: &str) -> bool {
+        false
+    }
+}
+
+/// Argon2id, the recommended default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Argon2idHasher {
+    pub memory_cost_kib: u32,
+    pub time_cost: u32,
+    pub parallelism: u32,
+}
+
+impl Default for Argon2idHasher {
+    fn default() -> Self {
+        Argon2idHasher {
+            memory_cost_kib: ARGON2_MEMORY_COST_KIB,
+            time_cost: ARGON2_TIME_COST,
+            parallelism: ARGON2_PARALLELISM,
+        }
+    }
+}
+
+impl PasswordHasher for Argon2idHasher {
+    fn can_verify(&self, hash: &str) -> bool {
+        hash.starts_with("$argon2")
+    }
+
+    fn hash_password(&self, password: &[u8]) -> Result<String, PasswordHashError> {
+        let params = Params::new(self.memory_cost_kib, self.time_cost, self.parallelism, None)?;
+        let salt = generate_salt()?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password(password, &salt)?
+            .to_string())
+    }
+
+    fn verify_password(&self, password: &[u8], hash: &str) -> Result<bool, PasswordHashError> {
+        verify_phc_hash(&Argon2::default(), password, hash)
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        let parsed = match PasswordHash::new(hash) {
+            Ok(parsed) => parsed,
+            Err(_) => return true,
+        };
+        if parsed.algorithm != Algorithm::Argon2id.ident() || parsed.version != Some(0x13) {
+            return true;
+        }
+        match Params::try_from(&parsed) {
+            Ok(params) => {
+                params.m_cost() != self.memory_cost_kib
+                    || params.t_cost() != self.time_cost
+                    || params.p_cost() != self.parallelism
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+/// scrypt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScryptHasher {
+    pub log_n: u8,
+    pub block_size: u32,
+    pub parallelism: u32,
+}
+
+impl Default for ScryptHasher {
+    fn default() -> Self {
+        ScryptHasher {
+            log_n: SCRYPT_LOG_N,
+            block_size: SCRYPT_BLOCK_SIZE,
+            parallelism: SCRYPT_PARALLELISM,
+        }
+    }
+}
+
+impl PasswordHasher for ScryptHasher {
+    fn can_verify(&self, hash: &str) -> bool {
+        hash.starts_with("$scrypt$")
+    }
+
+    fn hash_password(&self, password: &[u8]) -> Result<String, PasswordHashError> {
+        let params = scrypt::Params::new(
+            self.log_n,
+            self.block_size,
+            self.parallelism,
+            scrypt::Params::RECOMMENDED_LEN,
+        )
+        .map_err(|error| PasswordHashError(error.to_string()))?;
+        let salt = generate_salt()?;
+        Ok(Scrypt
+            .hash_password_customized(password, None, None, params, &salt)?
+            .to_string())
+    }
+
+    fn verify_password(&self, password: &[u8], hash: &str) -> Result<bool, PasswordHashError> {
+        verify_phc_hash(&Scrypt, password, hash)
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        let parsed = match PasswordHash::new(hash) {
+            Ok(parsed) => parsed,
+            Err(_) => return true,
+        };
+        match scrypt::Params::try_from(&parsed) {
+            Ok(params) => {
+                params.log_n() != self.log_n
+                    || params.r() != self.block_size
+                    || params.p() != self.parallelism
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+/// bcrypt. Only the first 72 bytes of a password are used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BcryptHasher {
+    pub cost: u32,
+}
+
+impl Default for BcryptHasher {
+    fn default() -> Self {
+        BcryptHasher { cost: BCRYPT_COST }
+    }
+}
+
+impl PasswordHasher for BcryptHasher {
+    fn can_verify(&self, hash: &str) -> bool {
+        ["$2a$", "$2b$", "$2x$", "$2y$"]
+            .iter()
+            .any(|prefix| hash.starts_with(prefix))
+    }
+
+    fn hash_password(&self, password: &[u8]) -> Result<String, PasswordHashError> {
+        Ok(bcrypt::hash(password, self.cost)?)
+    }
+
+    fn verify_password(&self, password: &[u8], hash: &str) -> Result<bool, PasswordHashError> {
+        Ok(bcrypt::verify(password, hash)?)
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        //  `$2b$<cost>$<salt and hash>`, new hashes always use the 2b version.
+        let mut parts = hash.split('$').skip(1);
+        match (parts.next(), parts.next()) {
+            (Some("2b"), Some(cost)) => cost.parse::<u32>() != Ok(self.cost),
+            _ => true,
+        }
+    }
+}
+
+/// PBKDF2-HMAC-SHA512, stored as `$pbkdf2-sha512$i=<iterations>,l=64$<salt>$<hash>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pbkdf2Hasher {
+    pub iterations: NonZeroU32,
+}
+
+impl Default for Pbkdf2Hasher {
+    fn default() -> Self {
+        Pbkdf2Hasher {
+            iterations: NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+        }
+    }
+}
+
+const PBKDF2_ID: &str = "pbkdf2-sha512";
+const PBKDF2_OUTPUT_LEN: usize = digest::SHA512_OUTPUT_LEN;
+
+impl PasswordHasher for Pbkdf2Hasher {
+    fn can_verify(&self, hash: &str) -> bool {
+        hash.starts_with(&format!("${}$", PBKDF2_ID))
+    }
+
+    fn hash_password(&self, password: &[u8]) -> Result<String, PasswordHashError> {
+        let mut salt = [0u8; SALT_LENGTH];
+        fill_random(&mut salt)?;
+        let mut pbkdf2_hash = [0u8; PBKDF2_OUTPUT_LEN];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA512,
+            self.iterations,
+            &salt,
+            password,
+            &mut pbkdf2_hash,
+        );
+        Ok(format!(
+            "${}$i={},l={}${}${}",
+            PBKDF2_ID,
+            self.iterations,
+            PBKDF2_OUTPUT_LEN,
+            BASE64_NOPAD.encode(&salt),
+            BASE64_NOPAD.encode(&pbkdf2_hash)
+        ))
+    }
+
+    fn verify_password(&self, password: &[u8], hash: &str) -> Result<bool, PasswordHashError> {
+        let parsed = parse_pbkdf2_hash(hash)?;
+        Ok(pbkdf2::verify(
+            pbkdf2::PBKDF2_HMAC_SHA512,
+            parsed.iterations,
+            &parsed.salt,
+            password,
+            &parsed.hash,
+        )
+        .is_ok())
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        match parse_pbkdf2_hash(hash) {
+            Ok(parsed) => parsed.iterations != self.iterations || parsed.salt.len() < SALT_LENGTH,
+            Err(_) => true,
+        }
+    }
+}
+
+/// Parts of a `$pbkdf2-sha512$...` hash. Parsed by hand because the salts of the hashes
+/// migrated from the old `salt` column are longer than the PHC parser accepts.
+pub(crate) struct Pbkdf2Hash {
+    pub iterations: NonZeroU32,
+    pub salt: Vec<u8>,
+    pub hash: Vec<u8>,
+}
+
+pub(crate) fn parse_pbkdf2_hash(hash: &str) -> Result<Pbkdf2Hash, PasswordHashError> {
+    let invalid = || PasswordHashError(String::from("Invalid pbkdf2-sha512 hash."));
+    let mut parts = hash.split('$');
+    //  The string starts with '$', so the first part is always empty.
+    match (parts.next(), parts.next()) {
+        (Some(""), Some(PBKDF2_ID)) => {}
+        _ => return Err(invalid()),
+    };
+    let params = parts.next().ok_or_else(invalid)?;
+    let salt = parts.next().ok_or_else(invalid)?;
+    let pbkdf2_hash = parts.next().ok_or_else(invalid)?;
+    if parts.next().is_some() {
+        return Err(invalid());
+    }
+    let iterations = params
+        .split(',')
+        .find_map(|param| param.strip_prefix("i="))
+        .and_then(|iterations| iterations.parse::<u32>().ok())
+        .and_then(NonZeroU32::new)
+        .ok_or_else(invalid)?;
+    Ok(Pbkdf2Hash {
+        iterations,
+        salt: BASE64_NOPAD
+            .decode(salt.as_bytes())
+            .map_err(|_| invalid())?,
+        hash: BASE64_NOPAD
+            .decode(pbkdf2_hash.as_bytes())
+            .map_err(|_| invalid())?,
+    })
+}
+
+fn verify_phc_hash(
+    verifier: &dyn PasswordVerifier,
+    password: &[u8],
+    hash: &str,
+) -> Result<bool, PasswordHashError> {
+    let parsed = PasswordHash::new(hash)?;
+    match verifier.verify_password(password, &parsed) {
+        Ok(()) => Ok(true),
+        Err(PhcError::Password) => Ok(false),
+        Err(error) => Err(error.into()),
+    }
+}
+
+fn generate_salt() -> Result<SaltString, PasswordHashError> {
+    let mut salt = [0u8; SALT_LENGTH];
+    fill_random(&mut salt)?;
+    Ok(SaltString::encode_b64(&salt)?)
+}
+
+fn fill_random(buffer: &mut [u8]) -> Result<(), PasswordHashError> {
+    SystemRandom::new()
+        .fill(buffer)
+        .map_err(|_| PasswordHashError(String::from("Failed to generate a random salt.")))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use data_encoding::BASE64;
+
+    const LEGACY_ITERATIONS: u32 = 1000;
+
+    /// Hash string built the way `migrations/105_phc_password_hashes.sql` builds it from the old columns:
+    /// a 64 byte salt and PBKDF2-SHA512 hash, both padded base64, with the padding trimmed.
+    pub(crate) fn legacy_migrated_hash(password: &str) -> String {
+        let salt = [7u8; digest::SHA512_OUTPUT_LEN];
+        let mut pbkdf2_hash = [0u8; PBKDF2_OUTPUT_LEN];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA512,
+            NonZeroU32::new(LEGACY_ITERATIONS).unwrap(),
+            &salt,
+            password.as_bytes(),
+            &mut pbkdf2_hash,
+        );
+        let salt_column = BASE64.encode(&salt);
+        let password_column = BASE64.encode(&pbkdf2_hash);
+        assert!(salt_column.ends_with('=') && password_column.ends_with('='));
+        format!(
+            "$pbkdf2-sha512$i=1000,l=64${}${}",
+            salt_column.trim_end_matches('='),
+            password_column.trim_end_matches('=')
+        )
+    }
+
+    #[test]
+    fn migrated_legacy_hash_parses() {
+        let parsed = parse_pbkdf2_hash(&legacy_migrated_hash("hunter22")).unwrap();
+        assert_eq!(parsed.iterations.get(), LEGACY_ITERATIONS);
+        assert_eq!(parsed.salt, vec![7u8; digest::SHA512_OUTPUT_LEN]);
+        assert_eq!(parsed.hash.len(), PBKDF2_OUTPUT_LEN);
+    }
+
+    #[test]
+    fn migrated_legacy_hash_verifies() {
+        let hash = legacy_migrated_hash("hunter22");
+        let hasher = Pbkdf2Hasher::default();
+        assert!(hasher.can_verify(&hash));
+        assert!(hasher.verify_password(b"hunter22", &hash).unwrap());
+        assert!(!hasher.verify_password(b"hunter23", &hash).unwrap());
+    }
+
+    #[test]
+    fn migrated_legacy_hash_needs_rehash() {
+        assert!(Pbkdf2Hasher::default().needs_rehash(&legacy_migrated_hash("hunter22")));
+    }
+
+    #[test]
+    fn parse_pbkdf2_hash_rejects_malformed_hashes() {
+        for hash in [
+            "",
+            "pbkdf2-sha512$i=1000,l=64$c2FsdA$aGFzaA",
+            "$pbkdf2-sha256$i=1000,l=64$c2FsdA$aGFzaA",
+            "$pbkdf2-sha512$l=64$c2FsdA$aGFzaA",
+            "$pbkdf2-sha512$i=0,l=64$c2FsdA$aGFzaA",
+            "$pbkdf2-sha512$i=1000,l=64$c2FsdA",
+            "$pbkdf2-sha512$i=1000,l=64$c2FsdA$aGFzaA$extra",
+            "$pbkdf2-sha512$i=1000,l=64$c2FsdA==$aGFzaA",
+        ] {
+            assert!(parse_pbkdf2_hash(hash).is_err(), "{} parsed", hash);
+        }
+    }
+
+    #[test]
+    fn pbkdf2_round_trip() {
+        let hasher = Pbkdf2Hasher {
+            iterations: NonZeroU32::new(10).unwrap(),
+        };
+        let hash = hasher.hash_password(b"hunter22").unwrap();
+        assert!(hash.starts_with("$pbkdf2-sha512$i=10,l=64$"));
+        assert!(hasher.verify_password(b"hunter22", &hash).unwrap());
+        assert!(!hasher.verify_password(b"hunter23", &hash).unwrap());
+        assert!(!hasher.needs_rehash(&hash));
+    }
+
+    #[test]
+    fn argon2id_round_trip() {
+        let hasher = Argon2idHasher {
+            memory_cost_kib: 64,
+            time_cost: 1,
+            parallelism: 1,
+        };
+        let hash = hasher.hash_password(b"hunter22").unwrap();
+        assert!(hasher.can_verify(&hash));
+        assert!(hasher.verify_password(b"hunter22", &hash).unwrap());
+        assert!(!hasher.verify_password(b"hunter23", &hash).unwrap());
+        assert!(!hasher.needs_rehash(&hash));
+        assert!(Argon2idHasher::default().needs_rehash(&hash));
+    }
+
+    #[test]
+    fn scrypt_round_trip() {
+        let hasher = ScryptHasher {
+            log_n: 4,
+            block_size: 8,
+            parallelism: 1,
+        };
+        let hash = hasher.hash_password(b"hunter22").unwrap();
+        assert!(hasher.can_verify(&hash));
+        assert!(hasher.verify_password(b"hunter22", &hash).unwrap());
+        assert!(!hasher.verify_password(b"hunter23", &hash).unwrap());
+        assert!(!hasher.needs_rehash(&hash));
+    }
+
+    #[test]
+    fn bcrypt_round_trip() {
+        let hasher = BcryptHasher { cost: 4 };
+        let hash = hasher.hash_password(b"hunter22").unwrap();
+        assert!(hasher.can_verify(&hash));
+        assert!(hasher.verify_password(b"hunter22", &hash).unwrap());
+        assert!(!hasher.verify_password(b"hunter23", &hash).unwrap());
+        assert!(!hasher.needs_rehash(&hash));
+        assert!(BcryptHasher::default().needs_rehash(&hash));
+    }
+}
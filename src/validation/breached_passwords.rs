@@ -0,0 +1,494 @@
+use data_encoding::{HEXLOWER_PERMISSIVE, HEXUPPER};
+use ring::digest;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const SHA1_LENGTH: usize = 20;
+const FILTER_MAGIC: &[u8; 4] = b"UBPF";
+const FILTER_VERSION: u8 = 1;
+const RANGE_PREFIX_LENGTH: usize = 5;
+/// 8 GiB, enough for every HaveIBeenPwned hash at a 0.1% false positive rate.
+pub const MAX_FILTER_BITS: u64 = 1 << 36;
+const MAX_FILTER_HASHES: u32 = 32;
+
+pub type Sha1Digest = [u8; SHA1_LENGTH];
+
+/// Local list of passwords known to be breached, looked up by their SHA-1 digest
+/// so it can be built from HaveIBeenPwned-style dumps. Nothing is sent over the network.
+/// Lookups may block on disk reads, the service functions run them on tokio's blocking threads.
+/// What a lookup error does is up to `AuthConfig::breached_passwords_fail_open`.
+pub trait BreachedPasswordList: Debug + Send + Sync {
+    fn contains_sha1(&self, sha1_digest: &Sha1Digest) -> io::Result<bool>;
+
+    fn contains_password(&self, password: &str) -> io::Result<bool> {
+        self.contains_sha1(&sha1(password))
+    }
+}
+
+/// HaveIBeenPwned-style hash list sorted by hash (one hex SHA-1 per line, optionally followed by `:count`),
+/// binary searched on disk, so even a full dump takes no memory. Lines must be sorted, like the "ordered by hash" downloads.
+#[derive(Debug)]
+pub struct SortedSha1List {
+    file: Mutex<File>,
+    length: u64,
+}
+
+impl SortedSha1List {
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<SortedSha1List> {
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        Ok(SortedSha1List {
+            file: Mutex::new(file),
+            length,
+        })
+    }
+
+    /// Binary search over byte offsets: each probe reads the first line that starts inside the searched range.
+    fn search(&self, sha1_digest: &Sha1Digest) -> io::Result<bool> {
+        let mut file = match self.file.lock() {
+            Ok(file) => file,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let mut low = 0;
+        let mut high = self.length;
+        let mut skipped = Vec::new();
+        let mut line = String::new();
+        while low < high {
+            let middle = low + (high - low) / 2;
+            //  Skips the rest of the line `middle` falls in, unless it's the start of the file
+            let skip_from = middle.saturating_sub(1);
+            file.seek(SeekFrom::Start(skip_from))?;
+            let mut reader = BufReader::new(&mut *file);
+            let start = if middle == 0 {
+                0
+            } else {
+                skipped.clear();
+                skip_from + reader.read_until(b'\n', &mut skipped)? as u64
+            };
+            if start >= high {
+                high = middle;
+                continue;
+            }
+            line.clear();
+            let line_length = reader.read_line(&mut line)? as u64;
+            match parse_hash_line(&line)? {
+                //  Only blank lines at the end of the file
+                None => high = middle,
+                Some(line_digest) if line_digest == *sha1_digest => return Ok(true),
+                Some(line_digest) if line_digest < *sha1_digest => low = start + line_length,
+                Some(_) => high = middle,
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl BreachedPasswordList for SortedSha1List {
+    fn contains_sha1(&self, sha1_digest: &Sha1Digest) -> io::Result<bool> {
+        self.search(sha1_digest)
+    }
+}
+
+/// Directory of k-anonymity range files, as the HaveIBeenPwned range API returns them:
+/// one file per 5 character hash prefix (`21BD1` or `21BD1.txt`) with a `SUFFIX:COUNT` line per hash.
+/// Only the file of the password's prefix is read, prefixes without a file have no breached passwords.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sha1RangeDirectory {
+    directory: PathBuf,
+}
+
+impl Sha1RangeDirectory {
+    pub fn new(directory: impl AsRef<Path>) -> io::Result<Sha1RangeDirectory> {
+        let directory = directory.as_ref().to_path_buf();
+        if !directory.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not a directory.", directory.display()),
+            ));
+        }
+        Ok(Sha1RangeDirectory { directory })
+    }
+
+    fn search(&self, sha1_digest: &Sha1Digest) -> io::Result<bool> {
+        let hash = HEXUPPER.encode(sha1_digest);
+        let (prefix, suffix) = hash.split_at(RANGE_PREFIX_LENGTH);
+        let range_file = match File::open(self.directory.join(format!("{}.txt", prefix))) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                match File::open(self.directory.join(prefix)) {
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+                    range_file => range_file?,
+                }
+            }
+            range_file => range_file?,
+        };
+        for line in BufReader::new(range_file).lines() {
+            let line = line?;
+            let line_suffix = line.split(':').next().unwrap_or_default().trim();
+            if line_suffix.eq_ignore_ascii_case(suffix) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl BreachedPasswordList for Sha1RangeDirectory {
+    fn contains_sha1(&self, sha1_digest: &Sha1Digest) -> io::Result<bool> {
+        self.search(sha1_digest)
+    }
+}
+
+/// Bloom filter of SHA-1 digests, a compact binary file built with `build_breached_password_filter`.
+/// It never misses a breached password, but rejects a small share of the others (the false positive rate it was built with).
+#[derive(Clone, PartialEq, Eq)]
+pub struct BreachedPasswordFilter {
+    hash_count: u32,
+    bit_count: u64,
+    bits: Vec<u8>,
+}
+
+impl Debug for BreachedPasswordFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BreachedPasswordFilter")
+            .field("hash_count", &self.hash_count)
+            .field("bit_count", &self.bit_count)
+            .finish()
+    }
+}
+
+impl BreachedPasswordFilter {
+    /// Empty filter sized for `expected_items` digests with the given false positive rate, which has to be between 0 and 1.
+    /// Fails if the filter would take more than `MAX_FILTER_BITS` bits.
+    pub fn new(
+        expected_items: u64,
+        false_positive_rate: f64,
+    ) -> io::Result<BreachedPasswordFilter> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The false positive rate has to be between 0 and 1.",
+            ));
+        }
+        let items = expected_items.max(1) as f64;
+        let ln_2 = std::f64::consts::LN_2;
+        let bit_count = (-items * false_positive_rate.ln() / (ln_2 * ln_2))
+            .ceil()
+            .max(8.0);
+        if bit_count > MAX_FILTER_BITS as f64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The breached password filter would be too big.",
+            ));
+        }
+        let bit_count = bit_count as u64;
+        let hash_count = ((bit_count as f64 / items) * ln_2)
+            .round()
+            .clamp(1.0, MAX_FILTER_HASHES as f64) as u32;
+        Ok(BreachedPasswordFilter {
+            hash_count,
+            bit_count,
+            bits: vec![0u8; bit_count.div_ceil(8) as usize],
+        })
+    }
+
+    pub fn insert_sha1(&mut self, sha1_digest: &Sha1Digest) {
+        for index in get_bit_indexes(sha1_digest, self.hash_count, self.bit_count) {
+            self.bits[(index / 8) as usize] |= 1 << (index % 8);
+        }
+    }
+
+    pub fn from_reader(mut reader: impl Read) -> io::Result<BreachedPasswordFilter> {
+        let mut header = [0u8; 17];
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != FILTER_MAGIC || header[4] != FILTER_VERSION {
+            return Err(invalid_data("Not a breached password filter file."));
+        }
+        let hash_count = u32::from_le_bytes(header[5..9].try_into().unwrap_or_default());
+        let bit_count = u64::from_le_bytes(header[9..17].try_into().unwrap_or_default());
+        if hash_count == 0 || bit_count == 0 {
+            return Err(invalid_data("Breached password filter file is empty."));
+        }
+        if hash_count > MAX_FILTER_HASHES || bit_count > MAX_FILTER_BITS {
+            return Err(invalid_data("Breached password filter file is too big."));
+        }
+        //  Grows with what's actually read, so a header claiming more than the file holds allocates nothing up front
+        let byte_count = bit_count.div_ceil(8);
+        let mut bits = Vec::new();
+        reader.take(byte_count).read_to_end(&mut bits)?;
+        if bits.len() as u64 != byte_count {
+            return Err(invalid_data("Breached password filter file is truncated."));
+        }
+        Ok(BreachedPasswordFilter {
+            hash_count,
+            bit_count,
+            bits,
+        })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<BreachedPasswordFilter> {
+        BreachedPasswordFilter::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(FILTER_MAGIC)?;
+        writer.write_all(&[FILTER_VERSION])?;
+        writer.write_all(&self.hash_count.to_le_bytes())?;
+        writer.write_all(&self.bit_count.to_le_bytes())?;
+        writer.write_all(&self.bits)?;
+        writer.flush()
+    }
+}
+
+impl BreachedPasswordList for BreachedPasswordFilter {
+    fn contains_sha1(&self, sha1_digest: &Sha1Digest) -> io::Result<bool> {
+        Ok(
+            get_bit_indexes(sha1_digest, self.hash_count, self.bit_count)
+                .all(|index| self.bits[(index / 8) as usize] & (1 << (index % 8)) != 0),
+        )
+    }
+}
+
+/// Builds a `BreachedPasswordFilter` file from a hash list (one hex SHA-1 per line, optionally followed by `:count`).
+/// The list is streamed, so `expected_items` (the amount of lines) sizes the filter up front.
+/// A 1% false positive rate takes about 1.2 bytes per hash. Returns the amount of hashes added.
+/// Fails before reading anything if the rate isn't between 0 and 1 or the filter would be too big.
+pub fn build_breached_password_filter(
+    hash_list: impl BufRead,
+    expected_items: u64,
+    false_positive_rate: f64,
+    output_path: impl AsRef<Path>,
+) -> io::Result<u64> {
+    let mut filter = BreachedPasswordFilter::new(expected_items, false_positive_rate)?;
+    let mut inserted = 0;
+    for line in hash_list.lines() {
+        if let Some(sha1_digest) = parse_hash_line(&line?)? {
+            filter.insert_sha1(&sha1_digest);
+            inserted += 1;
+        }
+    }
+    filter.write_to(BufWriter::new(File::create(output_path)?))?;
+    Ok(inserted)
+}
+
+/// Bit positions of a digest in a filter, by double hashing two 64 bit words of the (already uniform) SHA-1.
+fn get_bit_indexes(
+    sha1_digest: &Sha1Digest,
+    hash_count: u32,
+    bit_count: u64,
+) -> impl Iterator<Item = u64> {
+    let mut first_word = [0u8; 8];
+    let mut second_word = [0u8; 8];
+    first_word.copy_from_slice(&sha1_digest[0..8]);
+    second_word.copy_from_slice(&sha1_digest[8..16]);
+    let first_hash = u64::from_le_bytes(first_word);
+    let second_hash = u64::from_le_bytes(second_word) | 1;
+    (0..hash_count as u64)
+        .map(move |i| first_hash.wrapping_add(i.wrapping_mul(second_hash)) % bit_count)
+}
+
+pub fn sha1(password: &str) -> Sha1Digest {
+    let mut sha1_digest = [0u8; SHA1_LENGTH];
+    sha1_digest.copy_from_slice(
+        digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, password.as_bytes()).as_ref(),
+    );
+    sha1_digest
+}
+
+/// Digest of a `HASH` or `HASH:COUNT` line. Blank lines are skipped.
+fn parse_hash_line(line: &str) -> io::Result<Option<Sha1Digest>> {
+    let hash = line.split(':').next().unwrap_or_default().trim();
+    if hash.is_empty() {
+        return Ok(None);
+    }
+    let mut sha1_digest = [0u8; SHA1_LENGTH];
+    match HEXLOWER_PERMISSIVE.decode(hash.as_bytes()) {
+        Ok(bytes) if bytes.len() == SHA1_LENGTH => {
+            sha1_digest.copy_from_slice(&bytes);
+            Ok(Some(sha1_digest))
+        }
+        _ => Err(invalid_data(&format!("Invalid SHA-1 hash: {}", hash))),
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("user-lib-{}-{}", std::process::id(), name))
+    }
+
+    fn breached_hashes() -> Vec<String> {
+        let mut hashes: Vec<String> = (0..500)
+            .map(|i| HEXUPPER.encode(&sha1(&format!("breached{}", i))))
+            .collect();
+        hashes.sort();
+        hashes
+    }
+
+    fn write_sorted_list(name: &str, line_ending: &str) -> PathBuf {
+        let path = temp_path(name);
+        let contents: String = breached_hashes()
+            .iter()
+            .enumerate()
+            .map(|(i, hash)| format!("{}:{}{}", hash, i * 37, line_ending))
+            .collect();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn sorted_list_finds_every_listed_hash() {
+        let path = write_sorted_list("sorted-lf", "\n");
+        let list = SortedSha1List::from_file(&path).unwrap();
+        for i in 0..500 {
+            assert!(
+                list.contains_password(&format!("breached{}", i)).unwrap(),
+                "{}",
+                i
+            );
+        }
+        for i in 0..500 {
+            assert!(
+                !list.contains_password(&format!("safe{}", i)).unwrap(),
+                "{}",
+                i
+            );
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn sorted_list_reads_crlf_lines() {
+        let path = write_sorted_list("sorted-crlf", "\r\n");
+        let list = SortedSha1List::from_file(&path).unwrap();
+        assert!(list.contains_password("breached0").unwrap());
+        assert!(list.contains_password("breached499").unwrap());
+        assert!(!list.contains_password("safe").unwrap());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn sorted_list_handles_tiny_files() {
+        let path = temp_path("sorted-tiny");
+        fs::write(&path, "").unwrap();
+        assert!(!SortedSha1List::from_file(&path)
+            .unwrap()
+            .contains_password("password")
+            .unwrap());
+        fs::write(&path, HEXUPPER.encode(&sha1("password"))).unwrap();
+        let list = SortedSha1List::from_file(&path).unwrap();
+        assert!(list.contains_password("password").unwrap());
+        assert!(!list.contains_password("passwort").unwrap());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn range_directory_reads_the_prefix_file() {
+        let directory = temp_path("ranges");
+        fs::create_dir_all(&directory).unwrap();
+        let hash = HEXUPPER.encode(&sha1("breached"));
+        let other_hash = HEXUPPER.encode(&sha1("breached too"));
+        fs::write(
+            directory.join(format!("{}.txt", &hash[..5])),
+            format!(
+                "0000000000000000000000000000000000A:1\r\n{}:42\r\n",
+                &hash[5..]
+            ),
+        )
+        .unwrap();
+        fs::write(
+            directory.join(&other_hash[..5]),
+            format!("{}:7\n", other_hash[5..].to_lowercase()),
+        )
+        .unwrap();
+        let ranges = Sha1RangeDirectory::new(&directory).unwrap();
+        assert!(ranges.contains_password("breached").unwrap());
+        assert!(ranges.contains_password("breached too").unwrap());
+        assert!(!ranges.contains_password("safe").unwrap());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn filter_finds_inserted_hashes_after_a_round_trip() {
+        let mut filter = BreachedPasswordFilter::new(500, 0.001).unwrap();
+        for i in 0..500 {
+            filter.insert_sha1(&sha1(&format!("breached{}", i)));
+        }
+        let mut file = Vec::new();
+        filter.write_to(&mut file).unwrap();
+        let filter = BreachedPasswordFilter::from_reader(file.as_slice()).unwrap();
+        for i in 0..500 {
+            assert!(
+                filter.contains_password(&format!("breached{}", i)).unwrap(),
+                "{}",
+                i
+            );
+        }
+        let false_positives = (0..10000)
+            .filter(|i| filter.contains_password(&format!("safe{}", i)).unwrap())
+            .count();
+        assert!(false_positives < 50, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn filter_rejects_false_positive_rates_outside_zero_and_one() {
+        for false_positive_rate in [0.0, -0.5, 1.0, 2.0, f64::NAN, f64::INFINITY] {
+            assert!(
+                BreachedPasswordFilter::new(1000, false_positive_rate).is_err(),
+                "{}",
+                false_positive_rate
+            );
+        }
+    }
+
+    #[test]
+    fn filter_rejects_sizes_over_the_limit() {
+        assert!(BreachedPasswordFilter::new(u64::MAX, 0.01).is_err());
+    }
+
+    fn filter_header(hash_count: u32, bit_count: u64) -> Vec<u8> {
+        let mut header = FILTER_MAGIC.to_vec();
+        header.push(FILTER_VERSION);
+        header.extend_from_slice(&hash_count.to_le_bytes());
+        header.extend_from_slice(&bit_count.to_le_bytes());
+        header
+    }
+
+    #[test]
+    fn filter_file_rejects_invalid_headers() {
+        for header in [
+            filter_header(0, 64),
+            filter_header(3, 0),
+            filter_header(MAX_FILTER_HASHES + 1, 64),
+            filter_header(3, MAX_FILTER_BITS + 1),
+            filter_header(3, u64::MAX),
+            b"NOPE".to_vec(),
+        ] {
+            let mut file = header.clone();
+            file.extend_from_slice(&[0u8; 8]);
+            assert!(BreachedPasswordFilter::from_reader(file.as_slice()).is_err());
+        }
+    }
+
+    #[test]
+    fn filter_file_rejects_truncated_bits() {
+        let mut file = filter_header(3, MAX_FILTER_BITS);
+        file.extend_from_slice(&[0u8; 8]);
+        let error = BreachedPasswordFilter::from_reader(file.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn range_directory_must_exist() {
+        assert!(Sha1RangeDirectory::new(temp_path("missing-ranges")).is_err());
+    }
+}
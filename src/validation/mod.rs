@@ -1 +1,3 @@
+pub mod breached_passwords;
+pub mod password_strength;
 pub mod user_validator;
@@ -0,0 +1,177 @@
+use crate::resources::password_policy::{COMMON_PASSWORD_WORDS, KEYBOARD_ROWS};
+
+/// Estimates how hard a password is to guess, from 0 to 4, like zxcvbn's score:
+/// 0 is guessable in under a thousand tries, 1 in under a million, 2 in under a hundred million,
+/// 3 in under ten billion and 4 takes more than that.
+/// Common words (also with `@`, `0`, `3`... substitutions), repeated characters, alphabetical
+/// or numeric sequences and keyboard walks add almost nothing to the estimate.
+pub fn estimate_password_strength(password: &str) -> u8 {
+    let guesses_log10 = estimate_guesses_log10(password);
+    if guesses_log10 < 3.0 {
+        0
+    } else if guesses_log10 < 6.0 {
+        1
+    } else if guesses_log10 < 8.0 {
+        2
+    } else if guesses_log10 < 10.0 {
+        3
+    } else {
+        4
+    }
+}
+
+/// Base 10 logarithm of the guesses an attacker would need.
+fn estimate_guesses_log10(password: &str) -> f64 {
+    let lowercase: Vec<char> = password.to_lowercase().chars().collect();
+    let unleeted: Vec<char> = lowercase.iter().map(|c| unleet(*c)).collect();
+    let bits_per_character = get_cardinality(password).log2();
+
+    //  Characters that are part of a common word are covered by guessing the word from the list.
+    let mut covered = vec![false; unleeted.len()];
+    let mut bits = 0.0;
+    for word in COMMON_PASSWORD_WORDS {
+        let word: Vec<char> = word.chars().map(unleet).collect();
+        let mut start = 0;
+        while start + word.len() <= unleeted.len() {
+            let end = start + word.len();
+            if unleeted[start..end] == word[..] && !covered[start..end].contains(&true) {
+                covered[start..end].iter_mut().for_each(|c| *c = true);
+                //  Picking the word, plus a bit for its capitalization or substitutions
+                bits += (COMMON_PASSWORD_WORDS.len() as f64).log2() + 1.0;
+                start = end;
+            } else {
+                start += 1;
+            }
+        }
+    }
+
+    for (i, character) in lowercase.iter().enumerate() {
+        if covered[i] {
+            continue;
+        }
+        let predictable =
+            i > 0 && (*character == lowercase[i - 1] || is_sequence(lowercase[i - 1], *character));
+        bits += if predictable { 1.0 } else { bits_per_character };
+    }
+    bits * 2f64.log10()
+}
+
+/// Size of the alphabet the password's characters come from.
+fn get_cardinality(password: &str) -> f64 {
+    let mut cardinality = 0.0;
+    if password.chars().any(|c| c.is_ascii_lowercase()) {
+        cardinality += 26.0;
+    }
+    if password.chars().any(|c| c.is_ascii_uppercase()) {
+        cardinality += 26.0;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        cardinality += 10.0;
+    }
+    if password
+        .chars()
+        .any(|c| c.is_ascii_punctuation() || c == ' ')
+    {
+        cardinality += 33.0;
+    }
+    if !password.is_ascii() {
+        cardinality += 100.0;
+    }
+    f64::max(cardinality, 2.0)
+}
+
+/// Whether `current` follows `previous` in the alphabet, the digits or a keyboard row, in either direction.
+fn is_sequence(previous: char, current: char) -> bool {
+    if previous.is_ascii_alphanumeric()
+        && current.is_ascii_alphanumeric()
+        && (previous as u32).abs_diff(current as u32) == 1
+    {
+        return true;
+    }
+    KEYBOARD_ROWS.iter().any(|row| {
+        let row: Vec<char> = row.chars().collect();
+        row.windows(2).any(|pair| {
+            (pair[0] == previous && pair[1] == current)
+                || (pair[0] == current && pair[1] == previous)
+        })
+    })
+}
+
+fn unleet(character: char) -> char {
+    match character {
+        '@' | '4' => 'a',
+        '3' => 'e',
+        '1' | '!' => 'i',
+        '0' => 'o',
+        '$' | '5' => 's',
+        '7' => 't',
+        _ => character,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_and_tiny_passwords_score_zero() {
+        assert_eq!(estimate_password_strength(""), 0);
+        assert_eq!(estimate_password_strength("a"), 0);
+    }
+
+    #[test]
+    fn keyboard_walks_score_zero() {
+        assert_eq!(estimate_password_strength("qwertyui"), 0);
+    }
+
+    #[test]
+    fn common_words_sequences_and_repeats_score_one() {
+        for password in [
+            "password1",
+            "P@ssw0rd!",
+            "abcdefgh",
+            "12345678",
+            "aaaaaaaaaa",
+        ] {
+            assert_eq!(estimate_password_strength(password), 1, "{}", password);
+        }
+    }
+
+    #[test]
+    fn random_looking_passwords_score_higher() {
+        assert_eq!(estimate_password_strength("hunter22"), 3);
+        assert_eq!(estimate_password_strength("Tr0ub4dor&3"), 4);
+        assert_eq!(estimate_password_strength("correcthorsebattery"), 4);
+    }
+
+    #[test]
+    fn substitutions_dont_hide_common_words() {
+        assert_eq!(
+            estimate_password_strength("p@$$w0rd"),
+            estimate_password_strength("password")
+        );
+    }
+
+    #[test]
+    fn longer_passwords_never_score_lower() {
+        let mut password = String::from("x7");
+        let mut previous_score = estimate_password_strength(&password);
+        for character in "Kq9!mZ2#vL".chars() {
+            password.push(character);
+            let score = estimate_password_strength(&password);
+            assert!(score >= previous_score, "{}", password);
+            previous_score = score;
+        }
+        assert_eq!(previous_score, 4);
+    }
+
+    #[test]
+    fn sequences_go_both_ways() {
+        assert!(is_sequence('a', 'b'));
+        assert!(is_sequence('b', 'a'));
+        assert!(is_sequence('8', '9'));
+        assert!(is_sequence('w', 'q'));
+        assert!(!is_sequence('a', 'c'));
+        assert!(!is_sequence('9', ':'));
+    }
+}
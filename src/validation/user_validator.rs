@@ -1,87 +1,412 @@
+use crate::config::auth_config::AuthConfig;
 use crate::domain::credential::CredentialType;
+use crate::domain::error::UserLibError;
 use crate::dto::users::{UserLoginPayload, UserRegisterPayload};
-use crate::resources::error_messages::{ErrorResource, ERROR_INVALID_USERNAME};
-use crate::resources::{
-    error_messages::{
-        ERROR_INVALID_EMAIL, ERROR_INVALID_NAME, ERROR_INVALID_PASSWORD, ERROR_INVALID_PHONE_NUMBER,
-    },
-    variable_lengths::{
-        MAX_EMAIL_LENGTH, MAX_NAME_LENGTH, MAX_PASSWORD_LENGTH, MIN_EMAIL_LENGTH, MIN_NAME_LENGTH,
-        MIN_PASSWORD_LENGTH,
-    },
+use crate::resources::error_messages::{
+    ErrorResource, ERROR_CHECKING_BREACHED_PASSWORDS, ERROR_INVALID_EMAIL, ERROR_INVALID_NAME,
+    ERROR_INVALID_PASSWORD, ERROR_INVALID_PHONE_NUMBER, ERROR_INVALID_USERNAME,
+    ERROR_PASSWORD_BREACHED, ERROR_PASSWORD_CONTAINS_PERSONAL_INFORMATION,
+    ERROR_PASSWORD_MISSING_DIGIT, ERROR_PASSWORD_MISSING_LOWERCASE, ERROR_PASSWORD_MISSING_SYMBOL,
+    ERROR_PASSWORD_MISSING_UPPERCASE, ERROR_PASSWORD_TOO_MANY_REPEATED_CHARACTERS,
+    ERROR_PASSWORD_TOO_WEAK,
 };
+use crate::resources::password_policy::MIN_PERSONAL_INFORMATION_LENGTH;
+use crate::validation::breached_passwords::sha1;
+use crate::validation::password_strength::estimate_password_strength;
+use log::{error, warn};
+use std::io;
 
-fn validate_user_email(email: &String) -> bool {
-    email.len() >= MIN_EMAIL_LENGTH.into()
-        && email.len() <= MAX_EMAIL_LENGTH.into()
+fn validate_user_email(email: &str, config: &AuthConfig) -> bool {
+    email.len() >= config.min_email_length
+        && email.len() <= config.max_email_length
         && email.contains('@')
         && email.contains('.')
 }
-fn validate_user_phone_number(phone_number: &String) -> bool {
-    phone_number.len() <= CredentialType::get_max_length(&CredentialType::PhoneNumber)
-        && phone_number.len() >= CredentialType::get_min_length(&CredentialType::PhoneNumber)
+fn validate_user_phone_number(phone_number: &str, config: &AuthConfig) -> bool {
+    phone_number.len() <= config.get_max_credential_length(&CredentialType::PhoneNumber)
+        && phone_number.len() >= config.get_min_credential_length(&CredentialType::PhoneNumber)
 }
 
-fn validate_user_username(username: &String) -> bool {
-    username.len() >= CredentialType::get_max_length(&CredentialType::Username)
-        && username.len() <= CredentialType::get_min_length(&CredentialType::Username)
+fn validate_user_username(username: &str, config: &AuthConfig) -> bool {
+    username.len() >= config.get_min_credential_length(&CredentialType::Username)
+        && username.len() <= config.get_max_credential_length(&CredentialType::Username)
 }
-fn validate_user_name(name: &String) -> bool {
-    name.len() >= MIN_NAME_LENGTH.into() && name.len() <= MAX_NAME_LENGTH.into()
+fn validate_user_name(name: &str, config: &AuthConfig) -> bool {
+    name.len() >= config.min_name_length && name.len() <= config.max_name_length
 }
-fn validate_user_password(password: &String) -> bool {
-    password.len() >= MIN_PASSWORD_LENGTH.into() && password.len() <= MAX_PASSWORD_LENGTH.into()
+fn validate_user_password(password: &str, config: &AuthConfig) -> bool {
+    password.len() >= config.min_password_length && password.len() <= config.max_password_length
 }
 
 pub(crate) fn validate_user_for_creation(
     user: &UserRegisterPayload,
+    config: &AuthConfig,
     error_resources: &mut Vec<ErrorResource>,
 ) {
     for credential_dto in user.credentials.iter() {
         validate_credential(
             error_resources,
+            config,
             &credential_dto.credential,
             &credential_dto.credential_type,
         );
     }
 
-    if !validate_user_name(&user.name) {
+    if !validate_user_name(&user.name, config) {
         error_resources.push(ERROR_INVALID_NAME);
     }
-    if !validate_user_password(&user.password) {
+    let mut personal_information: Vec<&str> = user
+        .credentials
+        .iter()
+        .map(|credential_dto| credential_dto.credential.as_str())
+        .collect();
+    personal_information.push(&user.name);
+    validate_new_password(
+        error_resources,
+        config,
+        &user.password,
+        &personal_information,
+    );
+}
+
+/// Checks a password that's about to be set against the length limits and the `PasswordPolicy` of `config`.
+/// The breached password list is checked apart, by `validate_password_not_breached`.
+/// `personal_information` holds the user's name and credentials.
+pub(crate) fn validate_new_password(
+    error_resources: &mut Vec<ErrorResource>,
+    config: &AuthConfig,
+    password: &str,
+    personal_information: &[&str],
+) {
+    let policy = &config.password_policy;
+    if !validate_user_password(password, config) {
         error_resources.push(ERROR_INVALID_PASSWORD);
     }
+    if policy.require_lowercase && !password.chars().any(char::is_lowercase) {
+        error_resources.push(ERROR_PASSWORD_MISSING_LOWERCASE);
+    }
+    if policy.require_uppercase && !password.chars().any(char::is_uppercase) {
+        error_resources.push(ERROR_PASSWORD_MISSING_UPPERCASE);
+    }
+    if policy.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+        error_resources.push(ERROR_PASSWORD_MISSING_DIGIT);
+    }
+    if policy.require_symbol && password.chars().all(char::is_alphanumeric) {
+        error_resources.push(ERROR_PASSWORD_MISSING_SYMBOL);
+    }
+    if let Some(max_repeated_characters) = policy.max_repeated_characters {
+        if get_longest_repeated_run(password) > max_repeated_characters {
+            error_resources.push(ERROR_PASSWORD_TOO_MANY_REPEATED_CHARACTERS);
+        }
+    }
+    if policy.reject_personal_information
+        && contains_personal_information(password, personal_information)
+    {
+        error_resources.push(ERROR_PASSWORD_CONTAINS_PERSONAL_INFORMATION);
+    }
+    if estimate_password_strength(password) < policy.min_strength_score {
+        error_resources.push(ERROR_PASSWORD_TOO_WEAK);
+    }
 }
+
+/// Checks a password that's about to be set against the breached password list of `config`, if there is one.
+/// Lists can be read from disk, so the lookup runs on tokio's blocking threads.
+/// Fails with `ERROR_CHECKING_BREACHED_PASSWORDS` if the list can't be read, unless `config.breached_passwords_fail_open` is set.
+pub(crate) async fn validate_password_not_breached(
+    error_resources: &mut Vec<ErrorResource<'_>>,
+    config: &AuthConfig,
+    password: &str,
+) -> Result<(), UserLibError> {
+    let breached_passwords = match &config.breached_passwords {
+        None => return Ok(()),
+        Some(breached_passwords) => breached_passwords.clone(),
+    };
+    let sha1_digest = sha1(password);
+    let lookup_result =
+        match tokio::task::spawn_blocking(move || breached_passwords.contains_sha1(&sha1_digest))
+            .await
+        {
+            Ok(lookup_result) => lookup_result,
+            Err(e) => Err(io::Error::other(e)),
+        };
+    match lookup_result {
+        Ok(true) => error_resources.push(ERROR_PASSWORD_BREACHED),
+        Ok(false) => {}
+        Err(e) if config.breached_passwords_fail_open => {
+            warn!(
+                "Couldn't check the breached password list, accepting the password: {}",
+                e
+            );
+        }
+        Err(e) => {
+            error!("Couldn't check the breached password list: {}", e);
+            return Err(UserLibError::Internal {
+                resource: ERROR_CHECKING_BREACHED_PASSWORDS,
+                source: Some(Box::new(e)),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn get_longest_repeated_run(password: &str) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    let mut previous = None;
+    for character in password.chars() {
+        current = if previous == Some(character) {
+            current + 1
+        } else {
+            1
+        };
+        longest = longest.max(current);
+        previous = Some(character);
+    }
+    longest
+}
+
+/// Whether the password contains, ignoring case, any of the values or any word of them,
+/// like a single part of the name or of an email's local part.
+fn contains_personal_information(password: &str, personal_information: &[&str]) -> bool {
+    let password = password.to_lowercase();
+    personal_information
+        .iter()
+        .flat_map(|value| {
+            let value = value.to_lowercase();
+            //  Email domains are shared by lots of users, only the local part is personal.
+            let local_part = value.split('@').next().unwrap_or_default();
+            let mut fragments: Vec<String> = local_part
+                .split(|c: char| !c.is_alphanumeric())
+                .map(str::to_string)
+                .collect();
+            fragments.push(value);
+            fragments
+        })
+        .filter(|fragment| fragment.chars().count() >= MIN_PERSONAL_INFORMATION_LENGTH)
+        .any(|fragment| password.contains(&fragment))
+}
+
+/// Only rejects empty and oversized logins, so users whose credentials or passwords predate
+/// stricter length limits can still log in.
 pub(crate) fn validate_user_for_password_authentication(
     user: &UserLoginPayload,
+    config: &AuthConfig,
     error_resources: &mut Vec<ErrorResource>,
 ) {
-    validate_credential(error_resources, &user.credential, &user.credential_type);
-    if !validate_user_password(&user.password) {
+    if user.credential.is_empty()
+        || user.credential.len() > config.get_max_credential_length(&user.credential_type)
+    {
+        error_resources.push(match user.credential_type {
+            CredentialType::Email => ERROR_INVALID_EMAIL,
+            CredentialType::PhoneNumber => ERROR_INVALID_PHONE_NUMBER,
+            CredentialType::Username => ERROR_INVALID_USERNAME,
+        });
+    }
+    if user.password.is_empty() || user.password.len() > config.max_password_length {
         error_resources.push(ERROR_INVALID_PASSWORD);
     }
 }
 
-fn validate_credential(
+pub(crate) fn validate_credential(
     error_resources: &mut Vec<ErrorResource>,
-    credential: &String,
+    config: &AuthConfig,
+    credential: &str,
     credential_type: &CredentialType,
 ) {
     match credential_type {
         CredentialType::Email => {
-            if !validate_user_email(credential) {
+            if !validate_user_email(credential, config) {
                 error_resources.push(ERROR_INVALID_EMAIL);
             }
         }
         CredentialType::PhoneNumber => {
-            if !validate_user_phone_number(credential) {
+            if !validate_user_phone_number(credential, config) {
                 error_resources.push(ERROR_INVALID_PHONE_NUMBER);
             }
         }
         CredentialType::Username => {
-            if !validate_user_username(credential) {
+            if !validate_user_username(credential, config) {
                 error_resources.push(ERROR_INVALID_USERNAME);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::password_policy::PasswordPolicy;
+    use crate::validation::breached_passwords::{
+        BreachedPasswordFilter, BreachedPasswordList, Sha1Digest,
+    };
+
+    #[test]
+    fn usernames_between_the_length_limits_are_valid() {
+        let config = AuthConfig::builder().username_length(3, 8).build();
+        assert!(!validate_user_username("ab", &config));
+        assert!(validate_user_username("abc", &config));
+        assert!(validate_user_username("abcdefgh", &config));
+        assert!(!validate_user_username("abcdefghi", &config));
+    }
+
+    #[test]
+    fn login_only_rejects_empty_and_oversized_values() {
+        let config = AuthConfig::builder()
+            .username_length(5, 8)
+            .password_length(10, 12)
+            .build();
+        let login = |credential: &str, password: &str| {
+            let mut error_resources = Vec::new();
+            validate_user_for_password_authentication(
+                &UserLoginPayload {
+                    credential: credential.to_string(),
+                    credential_type: CredentialType::Username,
+                    password: password.to_string(),
+                },
+                &config,
+                &mut error_resources,
+            );
+            error_resources
+        };
+        assert!(login("abc", "short").is_empty());
+        assert_eq!(login("", "short"), vec![ERROR_INVALID_USERNAME]);
+        assert_eq!(login("abcdefghi", "short"), vec![ERROR_INVALID_USERNAME]);
+        assert_eq!(login("abc", ""), vec![ERROR_INVALID_PASSWORD]);
+        assert_eq!(login("abc", "abcdefghijklm"), vec![ERROR_INVALID_PASSWORD]);
+    }
+
+    #[test]
+    fn contains_personal_information_ignores_case() {
+        assert!(contains_personal_information(
+            "MyFranklin2020",
+            &["franklin"]
+        ));
+        assert!(contains_personal_information(
+            "myfranklin2020",
+            &["FRANKLIN"]
+        ));
+    }
+
+    #[test]
+    fn contains_personal_information_checks_each_word() {
+        let personal_information = ["Franklin Blanco", "franklin.blanco@example.com"];
+        assert!(contains_personal_information(
+            "blanco1987!",
+            &personal_information
+        ));
+        assert!(contains_personal_information(
+            "xFRANKLINx",
+            &personal_information
+        ));
+    }
+
+    #[test]
+    fn contains_personal_information_ignores_email_domains() {
+        assert!(!contains_personal_information(
+            "example-password",
+            &["bob@example.com"]
+        ));
+        assert!(contains_personal_information(
+            "bob@example.com!",
+            &["bob@example.com"]
+        ));
+    }
+
+    #[test]
+    fn contains_personal_information_ignores_short_fragments() {
+        assert!(!contains_personal_information("correct horse", &["Al Co"]));
+        assert!(contains_personal_information("xyzabc", &["abc"]));
+    }
+
+    #[test]
+    fn contains_personal_information_without_information() {
+        assert!(!contains_personal_information("anything", &[]));
+        assert!(!contains_personal_information("anything", &[""]));
+    }
+
+    #[test]
+    fn longest_repeated_run() {
+        assert_eq!(get_longest_repeated_run(""), 0);
+        assert_eq!(get_longest_repeated_run("abc"), 1);
+        assert_eq!(get_longest_repeated_run("aabbbbc"), 4);
+        assert_eq!(get_longest_repeated_run("ééé"), 3);
+    }
+
+    #[test]
+    fn default_policy_keeps_accepting_simple_passwords() {
+        let config = AuthConfig::default();
+        let mut error_resources = Vec::new();
+        validate_new_password(&mut error_resources, &config, "aaaa1234", &["aaaa1234"]);
+        assert!(error_resources.is_empty());
+    }
+
+    #[test]
+    fn recommended_policy_rejects_weak_repeated_and_personal_passwords() {
+        let config = AuthConfig::builder()
+            .password_policy(PasswordPolicy::recommended())
+            .build();
+        let mut error_resources = Vec::new();
+        validate_new_password(&mut error_resources, &config, "franklinnnn", &["Franklin"]);
+        assert_eq!(
+            error_resources,
+            vec![
+                ERROR_PASSWORD_TOO_MANY_REPEATED_CHARACTERS,
+                ERROR_PASSWORD_CONTAINS_PERSONAL_INFORMATION,
+            ]
+        );
+        error_resources.clear();
+        validate_new_password(&mut error_resources, &config, "12345678", &[]);
+        assert_eq!(error_resources, vec![ERROR_PASSWORD_TOO_WEAK]);
+    }
+
+    #[derive(Debug)]
+    struct UnreadableList;
+
+    impl BreachedPasswordList for UnreadableList {
+        fn contains_sha1(&self, _sha1_digest: &Sha1Digest) -> io::Result<bool> {
+            Err(io::Error::other("unreadable"))
+        }
+    }
+
+    #[tokio::test]
+    async fn breached_passwords_are_rejected() {
+        let mut filter = BreachedPasswordFilter::new(10, 0.01).unwrap();
+        filter.insert_sha1(&sha1("password"));
+        let config = AuthConfig::builder().breached_passwords(filter).build();
+        let mut error_resources = Vec::new();
+        validate_password_not_breached(&mut error_resources, &config, "password")
+            .await
+            .unwrap();
+        assert_eq!(error_resources, vec![ERROR_PASSWORD_BREACHED]);
+    }
+
+    #[tokio::test]
+    async fn unreadable_breached_passwords_fail_closed_by_default() {
+        let config = AuthConfig::builder()
+            .breached_passwords(UnreadableList)
+            .build();
+        let mut error_resources = Vec::new();
+        let result =
+            validate_password_not_breached(&mut error_resources, &config, "password").await;
+        assert!(matches!(
+            result,
+            Err(UserLibError::Internal {
+                resource: ERROR_CHECKING_BREACHED_PASSWORDS,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn unreadable_breached_passwords_can_fail_open() {
+        let config = AuthConfig::builder()
+            .breached_passwords(UnreadableList)
+            .breached_passwords_fail_open(true)
+            .build();
+        let mut error_resources = Vec::new();
+        validate_password_not_breached(&mut error_resources, &config, "password")
+            .await
+            .unwrap();
+        assert!(error_resources.is_empty());
+    }
+}
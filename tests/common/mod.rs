@@ -0,0 +1,41 @@
+//  Helpers shared by the tests that need a PostgreSQL database in DATABASE_URL.
+
+use sqlx::{Connection, Executor, PgConnection};
+use std::fs;
+
+async fn migrated_transaction(conn: &mut PgConnection, schema: &str) {
+    conn.execute(
+        format!(
+            "BEGIN; CREATE SCHEMA {0}; SET LOCAL search_path TO {0};",
+            schema
+        )
+        .as_str(),
+    )
+    .await
+    .unwrap();
+    let mut migrations: Vec<_> = fs::read_dir("migrations")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    migrations.sort();
+    for migration in migrations {
+        conn.execute(fs::read_to_string(&migration).unwrap().as_str())
+            .await
+            .unwrap_or_else(|e| panic!("{}: {}", migration.display(), e));
+    }
+}
+
+/// Connects to DATABASE_URL and runs every migration in `schema`, inside a transaction that's never committed,
+/// so tests don't see each other's rows. `None` when DATABASE_URL isn't set, so the test can be skipped.
+pub async fn connect(schema: &str) -> Option<PgConnection> {
+    let database_url = match std::env::var("DATABASE_URL") {
+        Ok(database_url) => database_url,
+        Err(_) => {
+            eprintln!("DATABASE_URL isn't set, skipping.");
+            return None;
+        }
+    };
+    let mut conn = PgConnection::connect(&database_url).await.unwrap();
+    migrated_transaction(&mut conn, schema).await;
+    Some(conn)
+}
@@ -0,0 +1,199 @@
+//  Needs a PostgreSQL database in DATABASE_URL, skipped without one. See `common::connect`.
+
+mod common;
+
+use common::connect;
+use sqlx::PgConnection;
+use std::num::NonZeroU32;
+use user_lib::config::auth_config::AuthConfig;
+use user_lib::domain::credential::{Credential, CredentialType};
+use user_lib::domain::error::UserLibError;
+use user_lib::dto::credential::CredentialDto;
+use user_lib::dto::token::AuthenticateUserDto;
+use user_lib::dto::users::UserRegisterPayload;
+use user_lib::resources::error_messages::{
+    ERROR_CANNOT_REMOVE_LAST_CREDENTIAL, ERROR_CREDENTIAL_TYPE_ALREADY_EXISTS,
+    ERROR_USER_ALREADY_EXISTS,
+};
+use user_lib::service::credential::{add_credential, remove_credential, update_credential};
+use user_lib::service::user::register_user;
+use user_lib::utils::password_hasher::Pbkdf2Hasher;
+
+fn config() -> AuthConfig {
+    AuthConfig::builder()
+        .password_hasher(Pbkdf2Hasher {
+            iterations: NonZeroU32::new(10).unwrap(),
+        })
+        .build()
+}
+
+fn credential(credential_type: CredentialType, credential: &str) -> CredentialDto {
+    CredentialDto {
+        credential: credential.to_string(),
+        credential_type,
+    }
+}
+
+async fn register(
+    conn: &mut PgConnection,
+    config: &AuthConfig,
+    credentials: Vec<CredentialDto>,
+) -> AuthenticateUserDto {
+    let token = register_user(
+        conn,
+        config,
+        UserRegisterPayload {
+            credentials,
+            password: "correct horse battery".to_string(),
+            name: "Credential User".to_string(),
+        },
+    )
+    .await
+    .unwrap();
+    AuthenticateUserDto {
+        id: token.user_id,
+        auth_token: token.auth_token,
+    }
+}
+
+fn assert_conflict(result: Result<Credential, UserLibError>, expected: (&str, &str)) {
+    match result {
+        Err(UserLibError::Conflict(error_resource)) => assert_eq!(error_resource, expected),
+        other => panic!("Expected {:?}, got {:?}", expected, other),
+    }
+}
+
+#[tokio::test]
+async fn the_last_credential_cant_be_removed() {
+    let Some(mut conn) = connect("credential_test").await else {
+        return;
+    };
+    let config = config();
+    let user = register(
+        &mut conn,
+        &config,
+        vec![credential(CredentialType::Username, "last_one")],
+    )
+    .await;
+    assert_conflict(
+        remove_credential(&mut conn, &config, user.clone(), CredentialType::Username).await,
+        ERROR_CANNOT_REMOVE_LAST_CREDENTIAL,
+    );
+
+    add_credential(
+        &mut conn,
+        &config,
+        user.clone(),
+        credential(CredentialType::Email, "last@example.com"),
+    )
+    .await
+    .unwrap();
+    remove_credential(&mut conn, &config, user.clone(), CredentialType::Username)
+        .await
+        .unwrap();
+    assert_conflict(
+        remove_credential(&mut conn, &config, user, CredentialType::Email).await,
+        ERROR_CANNOT_REMOVE_LAST_CREDENTIAL,
+    );
+}
+
+#[tokio::test]
+async fn credentials_of_other_users_cant_be_taken() {
+    let Some(mut conn) = connect("credential_test").await else {
+        return;
+    };
+    let config = config();
+    register(
+        &mut conn,
+        &config,
+        vec![credential(CredentialType::Email, "taken@example.com")],
+    )
+    .await;
+    let user = register(
+        &mut conn,
+        &config,
+        vec![credential(CredentialType::Username, "collider")],
+    )
+    .await;
+
+    //  Compared after canonicalization, so casing doesn't get around it
+    assert_conflict(
+        add_credential(
+            &mut conn,
+            &config,
+            user.clone(),
+            credential(CredentialType::Email, "Taken@Example.com"),
+        )
+        .await,
+        ERROR_USER_ALREADY_EXISTS,
+    );
+    add_credential(
+        &mut conn,
+        &config,
+        user.clone(),
+        credential(CredentialType::Email, "free@example.com"),
+    )
+    .await
+    .unwrap();
+    assert_conflict(
+        update_credential(
+            &mut conn,
+            &config,
+            user.clone(),
+            credential(CredentialType::Email, "taken@example.com"),
+        )
+        .await,
+        ERROR_USER_ALREADY_EXISTS,
+    );
+    assert_conflict(
+        add_credential(
+            &mut conn,
+            &config,
+            user,
+            credential(CredentialType::Email, "another@example.com"),
+        )
+        .await,
+        ERROR_CREDENTIAL_TYPE_ALREADY_EXISTS,
+    );
+}
+
+#[tokio::test]
+async fn changed_credentials_have_to_be_verified_again() {
+    let Some(mut conn) = connect("credential_test").await else {
+        return;
+    };
+    let config = config();
+    let user = register(
+        &mut conn,
+        &config,
+        vec![credential(CredentialType::Email, "verified@example.com")],
+    )
+    .await;
+    sqlx::query(r#"UPDATE "credential" SET validated = true WHERE user_id = $1;"#)
+        .bind(user.id)
+        .execute(&mut conn)
+        .await
+        .unwrap();
+
+    //  The same value, once canonicalized, isn't a change
+    let unchanged = update_credential(
+        &mut conn,
+        &config,
+        user.clone(),
+        credential(CredentialType::Email, "Verified@Example.com"),
+    )
+    .await
+    .unwrap();
+    assert!(unchanged.validated);
+
+    let changed = update_credential(
+        &mut conn,
+        &config,
+        user,
+        credential(CredentialType::Email, "changed@example.com"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(changed.credential, "changed@example.com");
+    assert!(!changed.validated);
+}
@@ -0,0 +1,159 @@
+//  Needs a PostgreSQL database in DATABASE_URL, skipped without one. See `common::connect`.
+
+mod common;
+
+use common::connect;
+use sqlx::Executor;
+use user_lib::config::auth_config::AuthConfig;
+use user_lib::domain::credential::CredentialType;
+use user_lib::domain::error::UserLibError;
+use user_lib::dto::credential::CredentialDto;
+use user_lib::dto::users::{UserLoginPayload, UserRegisterPayload};
+use user_lib::resources::error_messages::{
+    ERROR_CREDENTIAL_DOES_NOT_EXIST, ERROR_USER_ALREADY_EXISTS,
+};
+use user_lib::service::user::{password_login, register_user};
+
+const SHARED_TEXT: &str = "+18095551234";
+const PASSWORD: &str = "correct horse battery";
+
+fn register_payload(credential_type: CredentialType, name: &str) -> UserRegisterPayload {
+    UserRegisterPayload {
+        credentials: vec![CredentialDto {
+            credential: SHARED_TEXT.to_string(),
+            credential_type,
+        }],
+        password: PASSWORD.to_string(),
+        name: name.to_string(),
+    }
+}
+
+fn login_payload(credential_type: CredentialType) -> UserLoginPayload {
+    UserLoginPayload {
+        credential: SHARED_TEXT.to_string(),
+        credential_type,
+        password: PASSWORD.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn same_text_registers_and_logs_in_under_different_types() {
+    let Some(mut conn) = connect("credential_type_test").await else {
+        return;
+    };
+    let config = AuthConfig::default();
+
+    let phone_user = register_user(
+        &mut conn,
+        &config,
+        register_payload(CredentialType::PhoneNumber, "Phone User"),
+    )
+    .await
+    .unwrap();
+    let username_user = register_user(
+        &mut conn,
+        &config,
+        register_payload(CredentialType::Username, "Username User"),
+    )
+    .await
+    .unwrap();
+    assert_ne!(phone_user.user_id, username_user.user_id);
+
+    let phone_login = password_login(
+        &mut conn,
+        &config,
+        login_payload(CredentialType::PhoneNumber),
+    )
+    .await
+    .unwrap();
+    assert_eq!(phone_login.user_id, phone_user.user_id);
+    let username_login =
+        password_login(&mut conn, &config, login_payload(CredentialType::Username))
+            .await
+            .unwrap();
+    assert_eq!(username_login.user_id, username_user.user_id);
+}
+
+#[tokio::test]
+async fn login_only_looks_at_the_declared_type() {
+    let Some(mut conn) = connect("credential_type_test").await else {
+        return;
+    };
+    let config = AuthConfig::default();
+    register_user(
+        &mut conn,
+        &config,
+        register_payload(CredentialType::Username, "Username User"),
+    )
+    .await
+    .unwrap();
+
+    match password_login(
+        &mut conn,
+        &config,
+        login_payload(CredentialType::PhoneNumber),
+    )
+    .await
+    {
+        Err(UserLibError::Authentication(error_resource)) => {
+            assert_eq!(error_resource, ERROR_CREDENTIAL_DOES_NOT_EXIST)
+        }
+        other => panic!("Expected the phone number not to exist, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn same_type_and_text_still_conflicts() {
+    let Some(mut conn) = connect("credential_type_test").await else {
+        return;
+    };
+    let config = AuthConfig::default();
+    register_user(
+        &mut conn,
+        &config,
+        register_payload(CredentialType::Username, "First User"),
+    )
+    .await
+    .unwrap();
+
+    match register_user(
+        &mut conn,
+        &config,
+        register_payload(CredentialType::Username, "Second User"),
+    )
+    .await
+    {
+        Err(UserLibError::Conflict(error_resource)) => {
+            assert_eq!(error_resource, ERROR_USER_ALREADY_EXISTS)
+        }
+        other => panic!("Expected a conflict, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn database_enforces_uniqueness_per_type() {
+    let Some(mut conn) = connect("credential_type_test").await else {
+        return;
+    };
+    conn.execute(
+        r#"INSERT INTO "user" (name, password, time_created, last_updated) VALUES ('One', 'x', now(), now()), ('Two', 'x', now(), now());"#,
+    )
+    .await
+    .unwrap();
+    let insert = |user_name: &str, credential_type: &str| {
+        format!(
+            r#"INSERT INTO "credential" SELECT id, '{}', 'same', false, now(), now() FROM "user" WHERE name = '{}';"#,
+            credential_type, user_name
+        )
+    };
+    conn.execute(insert("One", "Username").as_str())
+        .await
+        .unwrap();
+    conn.execute(insert("Two", "PhoneNumber").as_str())
+        .await
+        .unwrap();
+    assert!(conn
+        .execute(insert("Two", "Username").as_str())
+        .await
+        .is_err());
+}
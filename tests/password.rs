@@ -0,0 +1,235 @@
+//  Needs a PostgreSQL database in DATABASE_URL, skipped without one. See `common::connect`.
+
+mod common;
+
+use common::connect;
+use sqlx::PgConnection;
+use std::num::NonZeroU32;
+use user_lib::config::auth_config::AuthConfig;
+use user_lib::config::password_policy::PasswordPolicy;
+use user_lib::domain::credential::CredentialType;
+use user_lib::domain::error::UserLibError;
+use user_lib::domain::token::Token;
+use user_lib::dto::credential::CredentialDto;
+use user_lib::dto::users::{UserLoginPayload, UserRegisterPayload, UserResetPasswordPayload};
+use user_lib::resources::error_messages::{ERROR_HASHING_PASSWORD, ERROR_PASSWORD_RECENTLY_USED};
+use user_lib::service::user::{
+    force_reset_password, password_login, register_user, reset_password,
+};
+use user_lib::utils::password_hasher::Pbkdf2Hasher;
+
+const USERNAME: &str = "password_user";
+const PASSWORD: &str = "correct horse battery";
+
+/// A cheap hasher, so the tests spend their time in the database and not hashing.
+fn fast_hasher() -> Pbkdf2Hasher {
+    Pbkdf2Hasher {
+        iterations: NonZeroU32::new(10).unwrap(),
+    }
+}
+
+async fn register(conn: &mut PgConnection, config: &AuthConfig) -> Token {
+    register_user(
+        conn,
+        config,
+        UserRegisterPayload {
+            credentials: vec![CredentialDto {
+                credential: USERNAME.to_string(),
+                credential_type: CredentialType::Username,
+            }],
+            password: PASSWORD.to_string(),
+            name: "Password User".to_string(),
+        },
+    )
+    .await
+    .unwrap()
+}
+
+async fn login(conn: &mut PgConnection, config: &AuthConfig) -> Result<Token, UserLibError> {
+    password_login(
+        conn,
+        config,
+        UserLoginPayload {
+            credential: USERNAME.to_string(),
+            credential_type: CredentialType::Username,
+            password: PASSWORD.to_string(),
+        },
+    )
+    .await
+}
+
+/// The stored hash of the user and the key id of its pepper.
+async fn get_stored_hash(conn: &mut PgConnection, user_id: i32) -> (String, Option<String>) {
+    sqlx::query_as(r#"SELECT password, pepper_key_id FROM "user" WHERE id = $1;"#)
+        .bind(user_id)
+        .fetch_one(conn)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn rotated_peppers_keep_verifying_and_are_replaced_on_login() {
+    let Some(mut conn) = connect("password_test").await else {
+        return;
+    };
+    let old_config = AuthConfig::builder()
+        .password_hasher(fast_hasher())
+        .password_pepper("old", b"old secret")
+        .build();
+    let registered = register(&mut conn, &old_config).await;
+    let (old_hash, old_key_id) = get_stored_hash(&mut conn, registered.user_id).await;
+    assert_eq!(old_key_id.as_deref(), Some("old"));
+
+    let rotated_config = AuthConfig::builder()
+        .password_hasher(fast_hasher())
+        .password_pepper("old", b"old secret")
+        .password_pepper("new", b"new secret")
+        .build();
+    login(&mut conn, &rotated_config).await.unwrap();
+    let (new_hash, new_key_id) = get_stored_hash(&mut conn, registered.user_id).await;
+    assert_eq!(new_key_id.as_deref(), Some("new"));
+    assert_ne!(new_hash, old_hash);
+
+    //  The old pepper can go once every hash was re-peppered
+    let new_only_config = AuthConfig::builder()
+        .password_hasher(fast_hasher())
+        .password_pepper("new", b"new secret")
+        .build();
+    login(&mut conn, &new_only_config).await.unwrap();
+}
+
+#[tokio::test]
+async fn hashes_with_an_unknown_pepper_fail_to_verify() {
+    let Some(mut conn) = connect("password_test").await else {
+        return;
+    };
+    let config = AuthConfig::builder()
+        .password_hasher(fast_hasher())
+        .password_pepper("removed", b"removed secret")
+        .build();
+    register(&mut conn, &config).await;
+
+    let config_without_pepper = AuthConfig::builder()
+        .password_hasher(fast_hasher())
+        .password_pepper("other", b"other secret")
+        .build();
+    match login(&mut conn, &config_without_pepper).await {
+        Err(UserLibError::Internal { resource, .. }) => {
+            assert_eq!(resource, ERROR_HASHING_PASSWORD)
+        }
+        other => panic!("Expected a hashing error, got {:?}", other),
+    }
+}
+
+async fn change_password(
+    conn: &mut PgConnection,
+    config: &AuthConfig,
+    user_id: i32,
+    password: &str,
+    new_password: &str,
+) -> Result<(), UserLibError> {
+    reset_password(
+        conn,
+        config,
+        UserResetPasswordPayload {
+            id: user_id,
+            password: password.to_string(),
+            new_password: new_password.to_string(),
+        },
+    )
+    .await
+    .map(|_| ())
+}
+
+fn assert_recently_used(result: Result<(), UserLibError>) {
+    match result {
+        Err(UserLibError::Validation(error_resource)) => {
+            assert_eq!(error_resource, ERROR_PASSWORD_RECENTLY_USED)
+        }
+        other => panic!("Expected the password to be recently used, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn password_history_is_trimmed_to_its_depth() {
+    let Some(mut conn) = connect("password_test").await else {
+        return;
+    };
+    let config = AuthConfig::builder()
+        .password_hasher(fast_hasher())
+        .password_policy(PasswordPolicy {
+            history_depth: 2,
+            ..PasswordPolicy::default()
+        })
+        .build();
+    let user_id = register(&mut conn, &config).await.user_id;
+    let passwords = [
+        PASSWORD,
+        "second password",
+        "third password",
+        "fourth password",
+    ];
+    for pair in passwords.windows(2) {
+        change_password(&mut conn, &config, user_id, pair[0], pair[1])
+            .await
+            .unwrap();
+    }
+    let history_length: i64 =
+        sqlx::query_scalar(r#"SELECT count(*) FROM "password_history" WHERE user_id = $1;"#)
+            .bind(user_id)
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+    assert_eq!(history_length, 2);
+
+    let current_password = passwords[3];
+    //  The current password and the two before it are blocked
+    for recent_password in &passwords[1..] {
+        assert_recently_used(
+            change_password(
+                &mut conn,
+                &config,
+                user_id,
+                current_password,
+                recent_password,
+            )
+            .await,
+        );
+    }
+    //  The first one was trimmed out of the history
+    change_password(&mut conn, &config, user_id, current_password, passwords[0])
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn password_history_can_be_skipped_on_force_reset() {
+    let Some(mut conn) = connect("password_test").await else {
+        return;
+    };
+    let policy = PasswordPolicy {
+        history_depth: 2,
+        ..PasswordPolicy::default()
+    };
+    let config = AuthConfig::builder()
+        .password_hasher(fast_hasher())
+        .password_policy(policy.clone())
+        .build();
+    let user_id = register(&mut conn, &config).await.user_id;
+    assert_recently_used(
+        force_reset_password(&mut conn, &config, &user_id, PASSWORD.to_string())
+            .await
+            .map(|_| ()),
+    );
+
+    let unchecked_config = AuthConfig::builder()
+        .password_hasher(fast_hasher())
+        .password_policy(PasswordPolicy {
+            check_history_on_force_reset: false,
+            ..policy
+        })
+        .build();
+    force_reset_password(&mut conn, &unchecked_config, &user_id, PASSWORD.to_string())
+        .await
+        .unwrap();
+}
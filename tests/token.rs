@@ -0,0 +1,317 @@
+//  Needs a PostgreSQL database in DATABASE_URL, skipped without one. See `common::connect`.
+
+mod common;
+
+use common::connect;
+use sqlx::PgConnection;
+use user_lib::config::auth_config::AuthConfig;
+use user_lib::domain::credential::CredentialType;
+use user_lib::domain::error::UserLibError;
+use user_lib::domain::token::Token;
+use user_lib::dto::credential::CredentialDto;
+use user_lib::dto::token::{AuthenticateUserDto, RefreshAuthTokenForUserDto};
+use user_lib::dto::users::{UserLoginPayload, UserRegisterPayload};
+use user_lib::resources::error_messages::{
+    ERROR_EXPIRED_REFRESH_TOKEN, ERROR_EXPIRED_SESSION, ERROR_INCORRECT_TOKEN,
+    ERROR_REUSED_REFRESH_TOKEN,
+};
+use user_lib::service::token::{list_sessions, revoke_session};
+use user_lib::service::user::{
+    authenticate_user, password_login, refresh_auth_token, register_user,
+};
+
+const PASSWORD: &str = "correct horse battery";
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+async fn register(conn: &mut PgConnection, config: &AuthConfig, username: &str) -> Token {
+    register_user(
+        conn,
+        config,
+        UserRegisterPayload {
+            credentials: vec![CredentialDto {
+                credential: username.to_string(),
+                credential_type: CredentialType::Username,
+            }],
+            password: PASSWORD.to_string(),
+            name: "Token User".to_string(),
+        },
+    )
+    .await
+    .unwrap()
+}
+
+async fn login(conn: &mut PgConnection, config: &AuthConfig, username: &str) -> Token {
+    password_login(
+        conn,
+        config,
+        UserLoginPayload {
+            credential: username.to_string(),
+            credential_type: CredentialType::Username,
+            password: PASSWORD.to_string(),
+        },
+    )
+    .await
+    .unwrap()
+}
+
+async fn refresh(
+    conn: &mut PgConnection,
+    config: &AuthConfig,
+    user_id: i32,
+    refresh_token: &str,
+) -> Result<Token, UserLibError> {
+    refresh_auth_token(
+        conn,
+        config,
+        RefreshAuthTokenForUserDto {
+            id: user_id,
+            refresh_token: refresh_token.to_string(),
+        },
+    )
+    .await
+}
+
+async fn authenticate(
+    conn: &mut PgConnection,
+    config: &AuthConfig,
+    token: &Token,
+) -> Result<(), UserLibError> {
+    authenticate_user(
+        conn,
+        config,
+        AuthenticateUserDto {
+            id: token.user_id,
+            auth_token: token.auth_token.clone(),
+        },
+    )
+    .await
+    .map(|_| ())
+}
+
+fn assert_token_error(result: Result<Token, UserLibError>, expected: (&str, &str)) {
+    match result {
+        Err(UserLibError::Token(error_resource)) => assert_eq!(error_resource, expected),
+        other => panic!("Expected {:?}, got {:?}", expected, other),
+    }
+}
+
+#[tokio::test]
+async fn refreshing_rotates_both_tokens() {
+    let Some(mut conn) = connect("token_test").await else {
+        return;
+    };
+    let config = AuthConfig::default();
+    let registered = register(&mut conn, &config, "rotation").await;
+
+    let refreshed = refresh(
+        &mut conn,
+        &config,
+        registered.user_id,
+        &registered.refresh_token,
+    )
+    .await
+    .unwrap();
+    assert_eq!(refreshed.id, registered.id);
+    assert_ne!(refreshed.auth_token, registered.auth_token);
+    assert_ne!(refreshed.refresh_token, registered.refresh_token);
+    authenticate(&mut conn, &config, &refreshed).await.unwrap();
+    assert!(authenticate(&mut conn, &config, &registered).await.is_err());
+    let retired_digests: Vec<String> =
+        sqlx::query_scalar("SELECT refresh_token FROM retired_refresh_token WHERE token_id = $1;")
+            .bind(registered.id)
+            .fetch_all(&mut conn)
+            .await
+            .unwrap();
+    assert_eq!(retired_digests.len(), 1);
+    //  Retired tokens are stored as digests too
+    assert_ne!(retired_digests[0], registered.refresh_token);
+
+    let refreshed_again = refresh(
+        &mut conn,
+        &config,
+        refreshed.user_id,
+        &refreshed.refresh_token,
+    )
+    .await
+    .unwrap();
+    assert_eq!(refreshed_again.id, registered.id);
+}
+
+#[tokio::test]
+async fn reusing_a_retired_refresh_token_revokes_the_session() {
+    let Some(mut conn) = connect("token_test").await else {
+        return;
+    };
+    let config = AuthConfig::default();
+    let registered = register(&mut conn, &config, "reuse").await;
+    let other_session = login(&mut conn, &config, "reuse").await;
+    assert_eq!(other_session.user_id, registered.user_id);
+    let refreshed = refresh(
+        &mut conn,
+        &config,
+        registered.user_id,
+        &registered.refresh_token,
+    )
+    .await
+    .unwrap();
+
+    assert_token_error(
+        refresh(
+            &mut conn,
+            &config,
+            registered.user_id,
+            &registered.refresh_token,
+        )
+        .await,
+        ERROR_REUSED_REFRESH_TOKEN,
+    );
+    //  The whole session is gone, including the tokens the legitimate refresh got
+    assert!(authenticate(&mut conn, &config, &refreshed).await.is_err());
+    assert_token_error(
+        refresh(
+            &mut conn,
+            &config,
+            refreshed.user_id,
+            &refreshed.refresh_token,
+        )
+        .await,
+        ERROR_INCORRECT_TOKEN,
+    );
+    //  Other sessions of the user are left alone
+    authenticate(&mut conn, &config, &other_session)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn refresh_tokens_only_work_for_their_own_user() {
+    let Some(mut conn) = connect("token_test").await else {
+        return;
+    };
+    let config = AuthConfig::default();
+    let owner = register(&mut conn, &config, "owner").await;
+    let other_user = register(&mut conn, &config, "other_user").await;
+
+    assert_token_error(
+        refresh(&mut conn, &config, other_user.user_id, &owner.refresh_token).await,
+        ERROR_INCORRECT_TOKEN,
+    );
+    //  The failed attempt neither rotated nor revoked the owner's session
+    authenticate(&mut conn, &config, &owner).await.unwrap();
+    let refreshed = refresh(&mut conn, &config, owner.user_id, &owner.refresh_token)
+        .await
+        .unwrap();
+    assert_eq!(refreshed.id, owner.id);
+    //  A retired token replayed under another user doesn't revoke the owner's session either
+    assert_token_error(
+        refresh(&mut conn, &config, other_user.user_id, &owner.refresh_token).await,
+        ERROR_INCORRECT_TOKEN,
+    );
+    authenticate(&mut conn, &config, &refreshed).await.unwrap();
+}
+
+async fn age_session(
+    conn: &mut PgConnection,
+    token: &Token,
+    created_days_ago: i32,
+    updated_days_ago: i32,
+) {
+    sqlx::query(
+        "UPDATE token SET time_created = now() - make_interval(days => $2), last_updated = now() - make_interval(days => $3) WHERE id = $1;",
+    )
+    .bind(token.id)
+    .bind(created_days_ago)
+    .bind(updated_days_ago)
+    .execute(conn)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn refresh_tokens_expire() {
+    let Some(mut conn) = connect("token_test").await else {
+        return;
+    };
+    let config = AuthConfig::builder()
+        .refresh_token_expiration_time_millis(10 * DAY_MILLIS)
+        .max_session_age_millis(100 * DAY_MILLIS)
+        .build();
+    let registered = register(&mut conn, &config, "expired").await;
+    age_session(&mut conn, &registered, 20, 11).await;
+
+    assert_token_error(
+        refresh(
+            &mut conn,
+            &config,
+            registered.user_id,
+            &registered.refresh_token,
+        )
+        .await,
+        ERROR_EXPIRED_REFRESH_TOKEN,
+    );
+
+    age_session(&mut conn, &registered, 20, 9).await;
+    refresh(
+        &mut conn,
+        &config,
+        registered.user_id,
+        &registered.refresh_token,
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn sessions_expire_even_when_refreshed() {
+    let Some(mut conn) = connect("token_test").await else {
+        return;
+    };
+    let config = AuthConfig::builder()
+        .refresh_token_expiration_time_millis(10 * DAY_MILLIS)
+        .max_session_age_millis(100 * DAY_MILLIS)
+        .build();
+    let registered = register(&mut conn, &config, "old_session").await;
+    age_session(&mut conn, &registered, 101, 1).await;
+
+    assert_token_error(
+        refresh(
+            &mut conn,
+            &config,
+            registered.user_id,
+            &registered.refresh_token,
+        )
+        .await,
+        ERROR_EXPIRED_SESSION,
+    );
+}
+
+#[tokio::test]
+async fn sessions_are_listed_without_their_tokens_and_can_be_revoked() {
+    let Some(mut conn) = connect("token_test").await else {
+        return;
+    };
+    let config = AuthConfig::default();
+    let first_session = register(&mut conn, &config, "sessions").await;
+    let second_session = login(&mut conn, &config, "sessions").await;
+
+    let sessions = list_sessions(&mut conn, &first_session.user_id)
+        .await
+        .unwrap();
+    let session_ids: Vec<i32> = sessions.iter().map(|session| session.id).collect();
+    assert_eq!(session_ids, vec![second_session.id, first_session.id]);
+    let listed = serde_json::to_value(&sessions[0]).unwrap();
+    assert!(listed.get("authToken").is_none());
+    assert!(listed.get("refreshToken").is_none());
+    //  Tokens carry the id their session is listed and revoked with
+    let issued = serde_json::to_value(&second_session).unwrap();
+    assert_eq!(issued["id"], second_session.id);
+
+    revoke_session(&mut conn, &first_session.user_id, &second_session.id)
+        .await
+        .unwrap();
+    let sessions = list_sessions(&mut conn, &first_session.user_id)
+        .await
+        .unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].id, first_session.id);
+}